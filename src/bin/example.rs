@@ -24,7 +24,7 @@ fn main() {
     graph.add_edge(mic, 0, del, 0).unwrap();
     graph.add_edge(del, 0, spk, 0).unwrap();
 
-    let lfo = graph.add_node(Oscillator::new(oscillator::Sine).freq(10.0));
+    let lfo = graph.add_node(Oscillator::new(oscillator::Sine(oscillator::Exact)).freq(10.0));
     let osc = graph.add_node(
         Oscillator::new(Tri(PolyBlep)).freq(440.0).lfo_intensity(0.1)
     );
@@ -13,7 +13,7 @@ fn main() {
     println!("Initializing signal chain...");
     let freq = 8000.0;
     let mut chains: Vec<DeviceChain> = Vec::new();
-    chains.push(DeviceChain::from(Oscillator::new(Sine).freq(freq))
+    chains.push(DeviceChain::from(Oscillator::new(Sine(Exact)).freq(freq))
         .into(WavWriter::new("wav/test_sine.wav", 1)));
     chains.push(DeviceChain::from(Oscillator::new(Saw(Aliased)).freq(freq))
         .into(WavWriter::new("wav/test_saw_naive.wav", 1)));
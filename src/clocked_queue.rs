@@ -0,0 +1,67 @@
+//! A timestamped, thread-safe sample queue.
+//!
+//! The `ClockedQueue` decouples synthesis from playback: the synthesis side
+//! fills it ahead of the playback clock, and the output callback pops the
+//! buffer whose `Time` matches (or is the nearest below) its own clock. This
+//! gives the signal chain a bounded look-ahead and graceful behavior under
+//! jitter, rather than locking synthesis to the rate the hardware consumes
+//! samples.
+//!
+//! The queue is an `Arc<Mutex<VecDeque<(Time, T)>>>`, so cloning it yields a
+//! second handle onto the same underlying queue, one for each side of the
+//! producer/consumer pair.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use types::Time;
+
+
+/// A thread-safe queue of timestamped buffers.
+pub struct ClockedQueue<T> {
+    queue: Arc<Mutex<VecDeque<(Time, T)>>>,
+}
+
+impl<T> Clone for ClockedQueue<T> {
+    fn clone(&self) -> Self {
+        ClockedQueue { queue: self.queue.clone() }
+    }
+}
+
+impl<T> ClockedQueue<T> {
+    /// Returns a new, empty queue.
+    pub fn new() -> Self {
+        ClockedQueue { queue: Arc::new(Mutex::new(VecDeque::new())) }
+    }
+
+    /// Pushes `buf`, timestamped with `clock`, onto the back of the queue.
+    pub fn push(&self, clock: Time, buf: T) {
+        self.queue.lock().unwrap().push_back((clock, buf));
+    }
+
+    /// Pops the oldest buffer, or `None` if the queue is empty.
+    pub fn pop_next(&self) -> Option<(Time, T)> {
+        self.queue.lock().unwrap().pop_front()
+    }
+
+    /// Pops the newest buffer, discarding any stale backlog ahead of it.
+    pub fn pop_latest(&self) -> Option<(Time, T)> {
+        let mut queue = self.queue.lock().unwrap();
+        let mut latest = None;
+        while let Some(item) = queue.pop_front() {
+            latest = Some(item);
+        }
+        latest
+    }
+
+    /// Pushes `buf` back onto the front of the queue, for a buffer that was
+    /// consumed too early.
+    pub fn unpop(&self, clock: Time, buf: T) {
+        self.queue.lock().unwrap().push_front((clock, buf));
+    }
+
+    /// Returns the timestamp of the oldest buffer without removing it.
+    pub fn peek_clock(&self) -> Option<Time> {
+        self.queue.lock().unwrap().front().map(|&(clock, _)| clock)
+    }
+}
@@ -0,0 +1,158 @@
+//! Accumulated per-channel MIDI controller state.
+//!
+//! The `MidiMessage` enum reports raw pitch bend, control changes, and channel
+//! pressure, but on their own these events are transient. `MidiState`
+//! accumulates them into usable state for each of the 16 MIDI channels: the
+//! current pitch bend converted to cents over a configurable bend range, the
+//! channel volume (CC7) and expression (CC11) as linear scalars, the sustain
+//! pedal (CC64), and the selected program. A global master volume scales every
+//! channel.
+//!
+//! Instruments query this state each `tick` to apply detune and gain to their
+//! voices, so that bend and volume CCs from a controller actually affect the
+//! sounding notes.
+
+use types::{MidiEvent, MidiMessage};
+
+
+/// The default pitch-bend range, in cents (two semitones).
+pub const DEFAULT_BEND_RANGE: f32 = 200.0;
+
+
+/// The accumulated controller state of a single MIDI channel.
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelState {
+    /// The current pitch bend, in cents.
+    pub bend: f32,
+    /// The channel volume (CC7), as a linear scalar.
+    pub volume: f32,
+    /// The expression (CC11), as a linear scalar.
+    pub expression: f32,
+    /// Whether the sustain pedal (CC64) is engaged.
+    pub sustain: bool,
+    /// The selected program number.
+    pub program: u8,
+}
+
+impl ChannelState {
+    fn new() -> ChannelState {
+        ChannelState {
+            bend: 0.0,
+            volume: 1.0,
+            expression: 1.0,
+            sustain: false,
+            program: 0,
+        }
+    }
+
+    /// Returns the combined channel gain, the product of volume and expression.
+    pub fn gain(&self) -> f32 {
+        self.volume*self.expression
+    }
+}
+
+
+/// Tracks the controller state of all 16 MIDI channels.
+pub struct MidiState {
+    channels: [ChannelState; 16],
+    bend_range: f32,
+    /// The global master volume, as a linear scalar.
+    pub master_volume: f32,
+}
+
+impl MidiState {
+    /// Returns a new state with a default bend range of ±200 cents.
+    pub fn new() -> MidiState {
+        MidiState::with_bend_range(DEFAULT_BEND_RANGE)
+    }
+
+    /// Returns a new state with the given pitch-bend range, in cents.
+    pub fn with_bend_range(bend_range: f32) -> MidiState {
+        MidiState {
+            channels: [ChannelState::new(); 16],
+            bend_range: bend_range,
+            master_volume: 1.0,
+        }
+    }
+
+    /// Updates the state from a MIDI event.
+    pub fn handle_event(&mut self, event: &MidiEvent) {
+        let channel = &mut self.channels[(event.channel & 0x0F) as usize];
+        match event.payload {
+            MidiMessage::PitchBend(bend) => {
+                channel.bend = bend*self.bend_range;
+            },
+            MidiMessage::ControlChange(7, value) => {
+                channel.volume = value as f32 / 127.0;
+            },
+            MidiMessage::ControlChange(11, value) => {
+                channel.expression = value as f32 / 127.0;
+            },
+            MidiMessage::ControlChange(64, value) => {
+                channel.sustain = value >= 64;
+            },
+            MidiMessage::SustainPedal(on) => {
+                channel.sustain = on;
+            },
+            MidiMessage::ProgramChange(program) => {
+                channel.program = program;
+            },
+            _ => ()
+        }
+    }
+
+    /// Returns the accumulated state of the given channel.
+    pub fn channel(&self, channel: u8) -> &ChannelState {
+        &self.channels[(channel & 0x0F) as usize]
+    }
+
+    /// Returns the pitch bend of the given channel, in cents.
+    pub fn bend(&self, channel: u8) -> f32 {
+        self.channel(channel).bend
+    }
+
+    /// Returns the channel gain scaled by the master volume.
+    pub fn gain(&self, channel: u8) -> f32 {
+        self.master_volume*self.channel(channel).gain()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use testing::flt_eq;
+    use types::{MidiEvent, MidiMessage};
+    use super::MidiState;
+
+    fn event(channel: u8, payload: MidiMessage) -> MidiEvent {
+        MidiEvent { channel: channel, time: 0, payload: payload }
+    }
+
+    #[test]
+    fn test_pitch_bend_cents() {
+        let mut state = MidiState::new();
+        state.handle_event(&event(0, MidiMessage::PitchBend(1.0)));
+        assert!(flt_eq(state.bend(0), 200.0));
+        state.handle_event(&event(0, MidiMessage::PitchBend(-0.5)));
+        assert!(flt_eq(state.bend(0), -100.0));
+    }
+
+    #[test]
+    fn test_channel_and_master_volume() {
+        let mut state = MidiState::new();
+        state.master_volume = 0.5;
+        state.handle_event(&event(3, MidiMessage::ControlChange(7, 127)));
+        state.handle_event(&event(3, MidiMessage::ControlChange(11, 64)));
+        assert!(flt_eq(state.gain(3), 0.5*64.0/127.0));
+    }
+
+    #[test]
+    fn test_sustain_cc() {
+        let mut state = MidiState::new();
+        assert!(!state.channel(0).sustain);
+        state.handle_event(&event(0, MidiMessage::ControlChange(64, 127)));
+        assert!(state.channel(0).sustain);
+        state.handle_event(&event(0, MidiMessage::ControlChange(64, 0)));
+        assert!(!state.channel(0).sustain);
+    }
+}
@@ -0,0 +1,256 @@
+//! Four-operator FM synthesis.
+//!
+//! This module builds on the phase-accumulator design in
+//! [`oscillator`](../oscillator/index.html) to provide classic four-operator
+//! FM synthesis in the style of the YM2612. Each operator is a sine phase
+//! accumulator paired with its own [`Adsr`](../adsr/index.html) envelope and a
+//! frequency multiplier relative to the played note.
+//!
+//! The defining behavior is phase modulation: when operator A modulates
+//! operator B, A's scaled output is added to B's phase argument before the sine
+//! is evaluated. An [`Algorithm`](enum.Algorithm.html) selects among the eight
+//! standard operator routings, from a fully serial stack through to the purely
+//! additive case. Operator 1 additionally supports a feedback term, routing the
+//! average of its own last two outputs back into its phase.
+//!
+//! ## Example
+//!
+//! ```
+//! use oxcable::fm::{Algorithm, Fm};
+//! let synth = Fm::new(Algorithm::Serial).feedback(4.0);
+//! ```
+
+use std::f32::consts::PI;
+use num::traits::Float;
+
+use adsr::{Adsr, AdsrMessage};
+use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, Time};
+
+
+/// The number of operators in the synth.
+const NUM_OPERATORS: usize = 4;
+
+
+/// Defines the messages that the FM synth supports.
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    /// Sets the base note, as a MIDI note number.
+    SetNote(u8),
+    /// Triggers the attack of every operator envelope.
+    NoteDown,
+    /// Triggers the release of every operator envelope.
+    NoteUp,
+}
+pub use self::Message::*;
+
+
+/// The operator routing algorithm.
+///
+/// Each variant names which operators modulate which, and which operators are
+/// summed into the final output. The operators are numbered 1 through 4.
+#[derive(Clone, Copy, Debug)]
+pub enum Algorithm {
+    /// `1→2→3→4`, a single serial stack. Output: 4.
+    Serial,
+    /// `1→3`, `2→3`, `3→4`. Output: 4.
+    DoubleMod,
+    /// `1→3`, `2→3`, `3→4` with 1 and 2 stacked. Output: 4.
+    Stacked,
+    /// `1→2`, `3→4`, two parallel stacks. Output: 2, 4.
+    TwoStacks,
+    /// `1→2`, `1→3`, `1→4`, one modulator driving three carriers. Output:
+    /// 2, 3, 4.
+    Fan,
+    /// `1→2`, with 3 and 4 parallel. Output: 2, 3, 4.
+    ModPlusPair,
+    /// `1→2`, with 3→4. Output: 2, 3, 4.
+    ModPlusStack,
+    /// All four operators in parallel, purely additive. Output: 1, 2, 3, 4.
+    Additive,
+}
+
+impl Algorithm {
+    /// Returns the modulation matrix and carrier mask for this algorithm.
+    ///
+    /// `mods[i][j]` is true when operator `j` modulates operator `i`, and
+    /// `carriers[i]` is true when operator `i` is summed into the output.
+    /// Every modulator has a lower index than the operator it modulates, so the
+    /// operators may be evaluated in order within a single frame.
+    fn routing(&self) -> ([[bool; NUM_OPERATORS]; NUM_OPERATORS], [bool; NUM_OPERATORS]) {
+        let mut mods = [[false; NUM_OPERATORS]; NUM_OPERATORS];
+        let mut carriers = [false; NUM_OPERATORS];
+        match *self {
+            Algorithm::Serial => {
+                mods[1][0] = true; mods[2][1] = true; mods[3][2] = true;
+                carriers[3] = true;
+            },
+            Algorithm::DoubleMod => {
+                mods[2][0] = true; mods[2][1] = true; mods[3][2] = true;
+                carriers[3] = true;
+            },
+            Algorithm::Stacked => {
+                mods[1][0] = true; mods[2][1] = true; mods[3][2] = true;
+                mods[2][0] = true;
+                carriers[3] = true;
+            },
+            Algorithm::TwoStacks => {
+                mods[1][0] = true; mods[3][2] = true;
+                carriers[1] = true; carriers[3] = true;
+            },
+            Algorithm::Fan => {
+                mods[1][0] = true; mods[2][0] = true; mods[3][0] = true;
+                carriers[1] = true; carriers[2] = true; carriers[3] = true;
+            },
+            Algorithm::ModPlusPair => {
+                mods[1][0] = true;
+                carriers[1] = true; carriers[2] = true; carriers[3] = true;
+            },
+            Algorithm::ModPlusStack => {
+                mods[1][0] = true; mods[3][2] = true;
+                carriers[1] = true; carriers[2] = true; carriers[3] = true;
+            },
+            Algorithm::Additive => {
+                carriers = [true; NUM_OPERATORS];
+            },
+        }
+        (mods, carriers)
+    }
+}
+
+
+/// A single FM operator: a sine accumulator with an envelope.
+struct Operator {
+    mult: f32,
+    phase: f32,
+    phase_delta: f32,
+    env: Adsr,
+    output: Sample,
+}
+
+impl Operator {
+    fn new(mult: f32) -> Operator {
+        Operator {
+            mult: mult,
+            phase: 0.0,
+            phase_delta: 0.0,
+            env: Adsr::default(1),
+            output: 0.0,
+        }
+    }
+
+    /// Sets the phase increment from the note's base frequency.
+    fn set_freq(&mut self, base_freq: f32) {
+        self.phase_delta = base_freq*self.mult*2.0*PI/(SAMPLE_RATE as f32);
+    }
+
+    /// Advances the operator one frame, modulating its phase by `mod_input`.
+    fn tick(&mut self, t: Time, mod_input: Sample) -> Sample {
+        self.phase += self.phase_delta;
+        if self.phase >= 2.0*PI {
+            self.phase -= 2.0*PI;
+        }
+        let raw = (self.phase + mod_input).sin();
+        let mut out = [0.0];
+        self.env.tick(t, &[raw], &mut out);
+        self.output = out[0];
+        self.output
+    }
+}
+
+
+/// A four-operator FM synthesizer.
+pub struct Fm {
+    operators: Vec<Operator>,
+    algorithm: Algorithm,
+    feedback: f32,
+    last_feedback: [Sample; 2],
+}
+
+impl Fm {
+    /// Returns a synth using the given routing, with unity frequency
+    /// multipliers and no feedback.
+    pub fn new(algorithm: Algorithm) -> Fm {
+        let operators = (0..NUM_OPERATORS).map(|_| Operator::new(1.0)).collect();
+        Fm {
+            operators: operators,
+            algorithm: algorithm,
+            feedback: 0.0,
+            last_feedback: [0.0; 2],
+        }
+    }
+
+    /// Sets the frequency multiplier of operator `op` (1-indexed).
+    pub fn multiplier(mut self, op: usize, mult: f32) -> Self {
+        self.operators[op-1].mult = mult;
+        self
+    }
+
+    /// Sets the operator 1 feedback amount, from 0 to 7.
+    pub fn feedback(mut self, feedback: f32) -> Self {
+        self.feedback = feedback;
+        self
+    }
+}
+
+impl MessageReceiver for Fm {
+    type Msg = Message;
+    fn handle_message(&mut self, msg: Message) {
+        match msg {
+            SetNote(note) => {
+                let base_freq = 440.0 * 2.0.powf((note as f32 - 69.0)/12.0);
+                for op in self.operators.iter_mut() {
+                    op.set_freq(base_freq);
+                }
+            },
+            NoteDown => {
+                for op in self.operators.iter_mut() {
+                    op.env.handle_message(AdsrMessage::NoteDown);
+                }
+            },
+            NoteUp => {
+                for op in self.operators.iter_mut() {
+                    op.env.handle_message(AdsrMessage::NoteUp);
+                }
+            },
+        }
+    }
+}
+
+impl AudioDevice for Fm {
+    fn num_inputs(&self) -> usize {
+        0
+    }
+
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn tick(&mut self, t: Time, _: &[Sample], outputs: &mut[Sample]) {
+        let (mods, carriers) = self.algorithm.routing();
+
+        let mut out = 0.0;
+        for i in 0..NUM_OPERATORS {
+            // Sum the outputs of any operators modulating this one.
+            let mut mod_input = 0.0;
+            for j in 0..i {
+                if mods[i][j] {
+                    mod_input += self.operators[j].output;
+                }
+            }
+            // Operator 1 feeds back the average of its last two outputs.
+            if i == 0 && self.feedback > 0.0 {
+                let avg = (self.last_feedback[0] + self.last_feedback[1]) / 2.0;
+                mod_input += self.feedback/7.0 * avg;
+            }
+            // Phase modulation is applied in radians.
+            let sample = self.operators[i].tick(t, mod_input*2.0*PI);
+            if carriers[i] {
+                out += sample;
+            }
+        }
+
+        self.last_feedback[1] = self.last_feedback[0];
+        self.last_feedback[0] = self.operators[0].output;
+        outputs[0] = out;
+    }
+}
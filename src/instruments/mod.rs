@@ -0,0 +1,8 @@
+//! Playable instruments driven by MIDI.
+
+pub use self::sampler::Sampler;
+pub use self::soundfont::SoundFont;
+
+mod sf2;
+pub mod sampler;
+pub mod soundfont;
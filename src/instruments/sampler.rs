@@ -0,0 +1,267 @@
+//! A polyphonic SoundFont (SF2) sampler.
+//!
+//! The sampler loads a SoundFont `.sf2` file and plays it as a MIDI-driven
+//! `AudioDevice`. The RIFF structure is parsed to recover the PCM sample pool
+//! and the instrument zones that map note ranges onto samples, including their
+//! root key and loop points.
+//!
+//! Playback is request based, modeled on the per-voice API used by progmidi:
+//! every `NoteOn` spawns a voice, and the caller may shape it through a
+//! [`VoiceRequest`](struct.VoiceRequest.html) builder before it starts
+//! sounding. Each voice resamples its mapped sample by the ratio
+//!
+//! ```text
+//! 2^((note - root_key)/12) * sample_rate / SAMPLE_RATE
+//! ```
+//!
+//! honoring the sample's loop points while the note is held.
+
+use std::fs::File;
+use std::io::Read;
+
+use num::traits::Float;
+
+use types::{SAMPLE_RATE, AudioDevice, MidiDevice, MidiMessage, Sample, Time};
+use super::sf2::{self, SampleData, Zone};
+
+
+/// A per-voice playback request.
+///
+/// Mirrors the fields of a progmidi voice request so callers can shape a note
+/// before it sounds. The request is copied into each voice when it starts.
+#[derive(Clone, Copy, Debug)]
+pub struct VoiceRequest {
+    volume: f32,
+    tune: f32,
+    hold_time: Option<f32>,
+    attack: f32,
+    release: f32,
+}
+
+impl VoiceRequest {
+    /// Returns a request with unity gain, no detune, and instant envelopes.
+    pub fn new() -> Self {
+        VoiceRequest {
+            volume: 1.0,
+            tune: 0.0,
+            hold_time: None,
+            attack: 0.0,
+            release: 0.0,
+        }
+    }
+
+    /// Sets the linear playback volume.
+    pub fn set_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    /// Adds a detune offset, in cents, to the playback ratio.
+    pub fn set_tune(mut self, cents: f32) -> Self {
+        self.tune = cents;
+        self
+    }
+
+    /// Sets a fixed hold time, in seconds, after which the voice releases
+    /// automatically.
+    pub fn set_hold_time(mut self, seconds: f32) -> Self {
+        self.hold_time = Some(seconds);
+        self
+    }
+
+    /// Sets the linear attack and release times, in seconds.
+    pub fn set_falloff(mut self, attack: f32, release: f32) -> Self {
+        self.attack = attack;
+        self.release = release;
+        self
+    }
+}
+
+
+/// The envelope state of a sounding voice.
+enum Stage { Attack, Sustain, Release }
+
+struct Voice {
+    sample: usize,
+    pos: f32,
+    ratio: f32,
+    volume: f32,
+    gain: f32,
+    gain_delta: f32,
+    stage: Stage,
+    release_delta: f32,
+    note: u8,
+    held_until: Option<Time>,
+}
+
+
+/// A polyphonic SoundFont sampler.
+pub struct Sampler<M: MidiDevice> {
+    midi: M,
+    samples: Vec<SampleData>,
+    zones: Vec<Zone>,
+    voices: Vec<Voice>,
+    request: VoiceRequest,
+}
+
+impl<M: MidiDevice> Sampler<M> {
+    /// Loads `path` as a SoundFont and plays it from the events of `midi`.
+    pub fn new(midi: M, path: &str) -> Result<Self, &'static str> {
+        let mut file = try!(File::open(path).map_err(|_| "failed to open soundfont"));
+        let mut bytes = Vec::new();
+        try!(file.read_to_end(&mut bytes).map_err(|_| "failed to read soundfont"));
+        let (samples, zones) = try!(sf2::parse(&bytes));
+        Ok(Sampler {
+            midi: midi,
+            samples: samples,
+            zones: zones,
+            voices: Vec::new(),
+            request: VoiceRequest::new(),
+        })
+    }
+
+    /// Sets the request template applied to subsequently triggered voices.
+    pub fn request(mut self, request: VoiceRequest) -> Self {
+        self.request = request;
+        self
+    }
+
+    /// Finds the zone covering `note`, if any.
+    fn zone_for(&self, note: u8) -> Option<usize> {
+        self.zones.iter().position(|z| z.lo_key <= note && note <= z.hi_key)
+    }
+
+    /// Spawns a voice for `note` using the current request template.
+    fn note_on(&mut self, note: u8, velocity: f32, t: Time) {
+        let zone = match self.zone_for(note) {
+            Some(z) => z,
+            None => return,
+        };
+        let sample = self.zones[zone].sample;
+        let header = &self.samples[sample];
+        let req = self.request;
+
+        // Derive the playback ratio from the note offset plus the tune offset.
+        let steps = (note as f32 - header.root_key as f32) + req.tune/100.0;
+        let ratio = 2.0.powf(steps/12.0) *
+            header.sample_rate as f32 / SAMPLE_RATE as f32;
+
+        let attack_samples = req.attack*SAMPLE_RATE as f32;
+        let release_samples = req.release*SAMPLE_RATE as f32;
+        let held_until = req.hold_time.map(|h| t + (h*SAMPLE_RATE as f32) as Time);
+
+        self.voices.push(Voice {
+            sample: sample,
+            pos: 0.0,
+            ratio: ratio,
+            volume: req.volume*velocity,
+            gain: if attack_samples > 0.0 { 0.0 } else { 1.0 },
+            gain_delta: if attack_samples > 0.0 { 1.0/attack_samples } else { 0.0 },
+            stage: Stage::Attack,
+            release_delta: if release_samples > 0.0 { 1.0/release_samples } else { 1.0 },
+            note: note,
+            held_until: held_until,
+        });
+    }
+
+    /// Begins the release ramp on any voice playing `note`.
+    fn note_off(&mut self, note: u8) {
+        for voice in self.voices.iter_mut() {
+            if voice.note == note {
+                voice.stage = Stage::Release;
+                voice.gain_delta = -voice.release_delta;
+            }
+        }
+    }
+}
+
+impl<M: MidiDevice> AudioDevice for Sampler<M> {
+    fn num_inputs(&self) -> usize {
+        0
+    }
+
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn tick(&mut self, t: Time, _: &[Sample], outputs: &mut[Sample]) {
+        // Handle any MIDI events scheduled for this frame.
+        for event in self.midi.get_events(t) {
+            match event.payload {
+                MidiMessage::NoteOn(note, vel) => self.note_on(note, vel, t),
+                MidiMessage::NoteOff(note, _) => self.note_off(note),
+                _ => ()
+            }
+        }
+
+        // Mix all active voices, freeing any that have finished.
+        let mut out = 0.0;
+        let samples = &self.samples;
+        self.voices.retain_mut_tick(t, samples, &mut out);
+        outputs[0] = out;
+    }
+}
+
+/// Helper for advancing and mixing voices, draining finished ones.
+trait VoiceTick {
+    fn retain_mut_tick(&mut self, t: Time, samples: &[SampleData], out: &mut Sample);
+}
+
+impl VoiceTick for Vec<Voice> {
+    fn retain_mut_tick(&mut self, t: Time, samples: &[SampleData], out: &mut Sample) {
+        let mut i = 0;
+        while i < self.len() {
+            let done = {
+                let voice = &mut self[i];
+                let header = &samples[voice.sample];
+
+                // Auto-release once the hold time expires.
+                if let Some(until) = voice.held_until {
+                    if t >= until {
+                        voice.stage = Stage::Release;
+                        voice.gain_delta = -voice.release_delta;
+                        voice.held_until = None;
+                    }
+                }
+
+                // Read the current sample with linear interpolation.
+                let idx = voice.pos.floor() as usize;
+                let s = if idx + 1 < header.data.len() {
+                    let frac = voice.pos - voice.pos.floor();
+                    header.data[idx]*(1.0-frac) + header.data[idx+1]*frac
+                } else {
+                    0.0
+                };
+                *out += s*voice.gain*voice.volume;
+
+                // Advance the envelope.
+                voice.gain += voice.gain_delta;
+                match voice.stage {
+                    Stage::Attack if voice.gain >= 1.0 => {
+                        voice.gain = 1.0;
+                        voice.gain_delta = 0.0;
+                        voice.stage = Stage::Sustain;
+                    },
+                    _ => ()
+                }
+
+                // Advance the read position, wrapping the sustain loop while
+                // the voice is not yet releasing.
+                voice.pos += voice.ratio;
+                let releasing = match voice.stage { Stage::Release => true, _ => false };
+                if !releasing && header.loop_end > header.loop_start &&
+                        voice.pos >= header.loop_end as f32 {
+                    voice.pos -= (header.loop_end - header.loop_start) as f32;
+                }
+
+                voice.pos as usize >= header.data.len() ||
+                    (releasing && voice.gain <= 0.0)
+            };
+            if done {
+                self.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Shared parsing of the SoundFont (SF2) RIFF structure.
+//!
+//! Both [`sampler`](../sampler/index.html) and
+//! [`soundfont`](../soundfont/index.html) load `.sf2` files, so the RIFF walk
+//! that recovers the PCM sample pool and the note-to-sample mapping lives here
+//! once rather than being copied into each device.
+//!
+//! The mapping is not a flat list of sample headers: a note resolves to a
+//! sample by walking the preset's zones down to the instrument zones whose
+//! `keyRange` generator covers it. This parser reads the `phdr`/`pbag`/`pgen`
+//! and `inst`/`ibag`/`igen` chunks to reproduce that hierarchy, falling back to
+//! a full-range mapping whenever the hierarchy resolves to no zones, as in
+//! degenerate files that carry no preset or instrument data.
+
+use std::io::{Cursor, Read};
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use types::{SAMPLE_RATE, Sample};
+
+
+/// A decoded PCM sample with its loop points and native rate.
+pub struct SampleData {
+    /// The decoded 16-bit PCM samples, normalized to `[-1.0, 1.0]`.
+    pub data: Vec<Sample>,
+    /// The MIDI note at which the sample plays back at its native pitch.
+    pub root_key: u8,
+    /// The start (inclusive) and end (exclusive) sample index of the sustain
+    /// loop.
+    pub loop_start: usize,
+    pub loop_end: usize,
+    /// The native sample rate of the recording, in Hz.
+    pub sample_rate: u32,
+}
+
+/// Maps a range of MIDI notes onto a sample in the pool.
+pub struct Zone {
+    pub lo_key: u8,
+    pub hi_key: u8,
+    pub sample: usize,
+}
+
+
+/// SF2 generator operators we care about, from the spec's `SFGenerator` enum.
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_SAMPLE_ID: u16 = 53;
+
+/// The raw `pdta` record vectors, before they are resolved into zones.
+struct Pdta {
+    phdr: Vec<u16>,            // preset bag indices
+    pbag: Vec<u16>,            // preset-zone generator indices
+    pgen: Vec<(u16, u16)>,     // (operator, amount)
+    inst: Vec<u16>,            // instrument bag indices
+    ibag: Vec<u16>,            // instrument-zone generator indices
+    igen: Vec<(u16, u16)>,     // (operator, amount)
+    shdr: Vec<(u32, u32, u32, u32, u32, u8)>, // start, end, loop start/end, rate, root
+}
+
+impl Pdta {
+    fn new() -> Pdta {
+        Pdta {
+            phdr: Vec::new(), pbag: Vec::new(), pgen: Vec::new(),
+            inst: Vec::new(), ibag: Vec::new(), igen: Vec::new(),
+            shdr: Vec::new(),
+        }
+    }
+}
+
+
+/// Parses `bytes` into the sample pool and the key-range zones mapping notes
+/// onto it.
+pub fn parse(bytes: &[u8]) -> Result<(Vec<SampleData>, Vec<Zone>), &'static str> {
+    let mut cur = Cursor::new(bytes);
+    if try!(read_tag(&mut cur)) != *b"RIFF" {
+        return Err("not a RIFF file");
+    }
+    let _riff_len = try!(cur.read_u32::<LittleEndian>().map_err(|_| "truncated"));
+    if try!(read_tag(&mut cur)) != *b"sfbk" {
+        return Err("not a soundfont");
+    }
+
+    let mut pcm: Vec<i16> = Vec::new();
+    let mut pdta = Pdta::new();
+
+    // Walk the top level LIST chunks.
+    while let Ok(tag) = read_tag(&mut cur) {
+        let len = try!(cur.read_u32::<LittleEndian>().map_err(|_| "truncated"));
+        let start = cur.position();
+        if tag == *b"LIST" {
+            let list_tag = try!(read_tag(&mut cur));
+            let end = start + len as u64;
+            if list_tag == *b"sdta" {
+                read_sdta(&mut cur, end, &mut pcm);
+            } else if list_tag == *b"pdta" {
+                read_pdta(&mut cur, end, &mut pdta);
+            }
+        }
+        cur.set_position(start + len as u64 + (len as u64 & 1));
+    }
+
+    // Decode the sample pool, keeping an shdr-index -> pool-index map so zones
+    // can reference samples even when some headers are skipped.
+    let mut samples = Vec::new();
+    let mut index_map = vec![None; pdta.shdr.len()];
+    for (i, &(s, e, ls, le, rate, root)) in pdta.shdr.iter().enumerate() {
+        let (s, e) = (s as usize, e as usize);
+        if e > pcm.len() || s >= e {
+            continue;
+        }
+        index_map[i] = Some(samples.len());
+        samples.push(SampleData {
+            data: pcm[s..e].iter().map(|&v| v as f32 / 32768.0).collect(),
+            root_key: root,
+            loop_start: (ls as usize).saturating_sub(s),
+            loop_end: (le as usize).saturating_sub(s),
+            sample_rate: rate,
+        });
+    }
+
+    let mut zones = build_zones(&pdta, &index_map);
+    if zones.is_empty() {
+        // Degenerate soundfonts without a preset/instrument hierarchy: map each
+        // sample across the full range so at least one voice can sound.
+        zones = (0..samples.len())
+            .map(|i| Zone { lo_key: 0, hi_key: 127, sample: i })
+            .collect();
+    }
+    Ok((samples, zones))
+}
+
+/// Resolves the preset -> instrument zone hierarchy into flat key-range zones.
+fn build_zones(pdta: &Pdta, index_map: &[Option<usize>]) -> Vec<Zone> {
+    let mut zones = Vec::new();
+    // The final phdr/inst record is a terminal marker, so each real entry pairs
+    // with its successor to bound its bag range.
+    for p in 0..pdta.phdr.len().saturating_sub(1) {
+        for bag in bag_range(&pdta.phdr, p) {
+            if bag + 1 >= pdta.pbag.len() {
+                break;
+            }
+            let gens = pdta.pbag[bag] as usize..pdta.pbag[bag + 1] as usize;
+            let mut range = (0u8, 127u8);
+            let mut instrument = None;
+            for g in gens {
+                if g >= pdta.pgen.len() { break; }
+                match pdta.pgen[g] {
+                    (GEN_KEY_RANGE, amt) => range = split_range(amt),
+                    (GEN_INSTRUMENT, amt) => instrument = Some(amt as usize),
+                    _ => {}
+                }
+            }
+            if let Some(inst) = instrument {
+                collect_instrument(pdta, inst, range, index_map, &mut zones);
+            }
+        }
+    }
+    zones
+}
+
+/// Appends the zones of instrument `inst`, inheriting the preset-level key
+/// range unless the instrument zone narrows it.
+fn collect_instrument(pdta: &Pdta, inst: usize, preset_range: (u8, u8),
+                      index_map: &[Option<usize>], zones: &mut Vec<Zone>) {
+    if inst + 1 >= pdta.inst.len() {
+        return;
+    }
+    for bag in bag_range(&pdta.inst, inst) {
+        if bag + 1 >= pdta.ibag.len() {
+            break;
+        }
+        let gens = pdta.ibag[bag] as usize..pdta.ibag[bag + 1] as usize;
+        let mut range = preset_range;
+        let mut sample = None;
+        for g in gens {
+            if g >= pdta.igen.len() { break; }
+            match pdta.igen[g] {
+                (GEN_KEY_RANGE, amt) => range = split_range(amt),
+                (GEN_SAMPLE_ID, amt) => sample = Some(amt as usize),
+                _ => {}
+            }
+        }
+        if let Some(sid) = sample {
+            if let Some(&Some(pool)) = index_map.get(sid) {
+                zones.push(Zone { lo_key: range.0, hi_key: range.1, sample: pool });
+            }
+        }
+    }
+}
+
+/// The half-open bag range for entry `i` of a phdr/inst index table.
+fn bag_range(table: &[u16], i: usize) -> ::std::ops::Range<usize> {
+    table[i] as usize..table[i + 1] as usize
+}
+
+/// Splits a `keyRange` generator amount into its low and high key bytes.
+fn split_range(amount: u16) -> (u8, u8) {
+    ((amount & 0xFF) as u8, (amount >> 8) as u8)
+}
+
+fn read_tag(cur: &mut Cursor<&[u8]>) -> Result<[u8; 4], &'static str> {
+    let mut tag = [0u8; 4];
+    try!(cur.read_exact(&mut tag).map_err(|_| "truncated tag"));
+    Ok(tag)
+}
+
+/// Reads the `sdta` PCM block into the sample pool.
+fn read_sdta(cur: &mut Cursor<&[u8]>, end: u64, pcm: &mut Vec<i16>) {
+    while cur.position() < end {
+        let tag = match read_tag(cur) { Ok(t) => t, Err(_) => break };
+        let len = match cur.read_u32::<LittleEndian>() { Ok(l) => l, Err(_) => break };
+        let start = cur.position();
+        if tag == *b"smpl" {
+            for _ in 0..(len/2) {
+                match cur.read_i16::<LittleEndian>() {
+                    Ok(v) => pcm.push(v),
+                    Err(_) => break,
+                }
+            }
+        }
+        cur.set_position(start + len as u64);
+    }
+}
+
+/// Reads the preset, instrument, and sample-header records out of `pdta`.
+fn read_pdta(cur: &mut Cursor<&[u8]>, end: u64, pdta: &mut Pdta) {
+    while cur.position() < end {
+        let tag = match read_tag(cur) { Ok(t) => t, Err(_) => break };
+        let len = match cur.read_u32::<LittleEndian>() { Ok(l) => l, Err(_) => break };
+        let start = cur.position();
+        match &tag {
+            b"phdr" => for _ in 0..(len/38) {
+                // achPresetName[20], wPreset, wBank, wPresetBagNdx, then 12
+                // bytes of library/genre/morphology.
+                if skip(cur, 20).is_err() { break; }
+                let _preset = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                let _bank = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                pdta.phdr.push(cur.read_u16::<LittleEndian>().unwrap_or(0));
+                let _ = skip(cur, 12);
+            },
+            b"pbag" => for _ in 0..(len/4) {
+                pdta.pbag.push(cur.read_u16::<LittleEndian>().unwrap_or(0));
+                let _mod = cur.read_u16::<LittleEndian>().unwrap_or(0);
+            },
+            b"pgen" => for _ in 0..(len/4) {
+                let oper = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                let amt = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                pdta.pgen.push((oper, amt));
+            },
+            b"inst" => for _ in 0..(len/22) {
+                // achInstName[20], wInstBagNdx.
+                if skip(cur, 20).is_err() { break; }
+                pdta.inst.push(cur.read_u16::<LittleEndian>().unwrap_or(0));
+            },
+            b"ibag" => for _ in 0..(len/4) {
+                pdta.ibag.push(cur.read_u16::<LittleEndian>().unwrap_or(0));
+                let _mod = cur.read_u16::<LittleEndian>().unwrap_or(0);
+            },
+            b"igen" => for _ in 0..(len/4) {
+                let oper = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                let amt = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                pdta.igen.push((oper, amt));
+            },
+            b"shdr" => for _ in 0..(len/46) {
+                let mut name = [0u8; 20];
+                if cur.read_exact(&mut name).is_err() { break; }
+                let s = cur.read_u32::<LittleEndian>().unwrap_or(0);
+                let e = cur.read_u32::<LittleEndian>().unwrap_or(0);
+                let ls = cur.read_u32::<LittleEndian>().unwrap_or(0);
+                let le = cur.read_u32::<LittleEndian>().unwrap_or(0);
+                let rate = cur.read_u32::<LittleEndian>().unwrap_or(SAMPLE_RATE);
+                let root = cur.read_u8().unwrap_or(60);
+                let _correction = cur.read_i8().unwrap_or(0);
+                let _link = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                let _typ = cur.read_u16::<LittleEndian>().unwrap_or(0);
+                pdta.shdr.push((s, e, ls, le, rate, root));
+            },
+            _ => {}
+        }
+        cur.set_position(start + len as u64);
+    }
+}
+
+/// Skips `n` bytes of the cursor, erroring if the stream ends early.
+fn skip(cur: &mut Cursor<&[u8]>, n: usize) -> Result<(), ()> {
+    let mut buf = vec![0u8; n];
+    cur.read_exact(&mut buf).map_err(|_| ())
+}
@@ -0,0 +1,234 @@
+//! A multisampled SoundFont instrument built on `VoiceArray`.
+//!
+//! Where [`sampler`](../sampler/index.html) exposes a progmidi-style per-note
+//! request API, this device is driven directly by `MidiMessage` events and
+//! manages its polyphony through a [`VoiceArray`](../../voice_array/index.html).
+//! It parses the RIFF structure of an SF2 file, resolves each note to a sample
+//! by walking the preset zones down to the instrument zones whose key range
+//! covers the note, and renders by linearly-interpolated resampling from the
+//! sample's native rate to `SAMPLE_RATE`. The zones of every preset in the
+//! file are searched together and the first one covering the note is used;
+//! the channel's program number does not select a preset.
+//!
+//! On `note_on` a voice is loaned a playback request carrying its playback
+//! rate, a linear volume scalar, and a falloff envelope with separate attack
+//! and release rates. On `note_off` the voice keeps rendering through its
+//! sustain loop while the release ramp decays to silence, and is freed only
+//! once silent.
+
+use std::fs::File;
+use std::io::Read;
+
+use num::traits::Float;
+
+use types::{SAMPLE_RATE, AudioDevice, MidiDevice, MidiMessage, Sample, Time};
+use controller::MidiState;
+use voice_array::VoiceArray;
+use super::sf2::{self, SampleData, Zone};
+
+
+/// A single sounding voice.
+#[derive(Clone)]
+struct Voice {
+    sample: usize,
+    channel: u8,
+    pos: f32,
+    rate: f32,
+    volume: f32,
+    gain: f32,
+    attack_rate: f32,
+    release_rate: f32,
+    releasing: bool,
+    active: bool,
+}
+
+impl Voice {
+    fn new() -> Voice {
+        Voice {
+            sample: 0,
+            channel: 0,
+            pos: 0.0,
+            rate: 1.0,
+            volume: 0.0,
+            gain: 0.0,
+            attack_rate: 1.0,
+            release_rate: 1.0,
+            releasing: false,
+            active: false,
+        }
+    }
+}
+
+
+/// A polyphonic, multisampled SoundFont instrument.
+pub struct SoundFont<M: MidiDevice> {
+    midi: M,
+    state: MidiState,
+    samples: Vec<SampleData>,
+    zones: Vec<Zone>,
+    voices: VoiceArray<Voice>,
+    attack: f32,
+    release: f32,
+    tune: f32,
+}
+
+impl<M: MidiDevice> SoundFont<M> {
+    /// Loads `path` and plays it from the events of `midi` across `num_voices`
+    /// voices.
+    pub fn new(midi: M, path: &str, num_voices: usize)
+            -> Result<Self, &'static str> {
+        let mut file = try!(File::open(path).map_err(|_| "failed to open soundfont"));
+        let mut bytes = Vec::new();
+        try!(file.read_to_end(&mut bytes).map_err(|_| "failed to read soundfont"));
+        let (samples, zones) = try!(sf2::parse(&bytes));
+        let voices = (0..num_voices).map(|_| Voice::new()).collect();
+        Ok(SoundFont {
+            midi: midi,
+            state: MidiState::new(),
+            samples: samples,
+            zones: zones,
+            voices: VoiceArray::new(voices),
+            attack: 0.005,
+            release: 0.1,
+            tune: 0.0,
+        })
+    }
+
+    /// Sets the attack and release times, in seconds.
+    pub fn falloff(mut self, attack: f32, release: f32) -> Self {
+        self.attack = attack;
+        self.release = release;
+        self
+    }
+
+    /// Sets a detune offset, in cents, applied to every note.
+    pub fn tune(mut self, cents: f32) -> Self {
+        self.tune = cents;
+        self
+    }
+
+    /// Finds the sample covering `note`, if any.
+    fn sample_for(&self, note: u8) -> Option<usize> {
+        self.zones.iter()
+            .find(|z| z.lo_key <= note && note <= z.hi_key)
+            .map(|z| z.sample)
+    }
+
+    fn handle_note_on(&mut self, channel: u8, note: u8, velocity: f32) {
+        let sample = match self.sample_for(note) { Some(s) => s, None => return };
+        let root = self.samples[sample].root_key;
+        let native = self.samples[sample].sample_rate;
+        let steps = (note as f32 - root as f32) + self.tune/100.0;
+        let rate = 2.0.powf(steps/12.0) * native as f32 / SAMPLE_RATE as f32;
+        let attack_rate = if self.attack > 0.0 {
+            1.0 / (self.attack*SAMPLE_RATE as f32)
+        } else {
+            1.0
+        };
+        let release_rate = if self.release > 0.0 {
+            1.0 / (self.release*SAMPLE_RATE as f32)
+        } else {
+            1.0
+        };
+
+        let voice = self.voices.note_on(note);
+        voice.sample = sample;
+        voice.channel = channel;
+        voice.pos = 0.0;
+        voice.rate = rate;
+        voice.volume = velocity;
+        voice.gain = 0.0;
+        voice.attack_rate = attack_rate;
+        voice.release_rate = release_rate;
+        voice.releasing = false;
+        voice.active = true;
+    }
+
+    fn handle_note_off(&mut self, note: u8) {
+        if let Some(voice) = self.voices.sustained_note_off(note) {
+            // Pedal up: begin the release ramp. The voice keeps its slot and
+            // renders on until it fades, when `free_silent` reclaims it. With
+            // the pedal down the note is held instead and nothing is returned.
+            voice.releasing = true;
+        }
+    }
+
+    fn set_pedal(&mut self, on: bool) {
+        // Lifting the pedal releases every note it was holding; start each
+        // one's release ramp.
+        for i in self.voices.set_sustain(on) {
+            self.voices.voice_mut(i).releasing = true;
+        }
+    }
+}
+
+impl<M: MidiDevice> AudioDevice for SoundFont<M> {
+    fn num_inputs(&self) -> usize {
+        0
+    }
+
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn tick(&mut self, t: Time, _: &[Sample], outputs: &mut[Sample]) {
+        for event in self.midi.get_events(t) {
+            // Accumulate controller state first, so bend and gain CCs seen this
+            // tick apply to the notes they arrive alongside.
+            self.state.handle_event(&event);
+            match event.payload {
+                MidiMessage::NoteOn(note, vel) =>
+                    self.handle_note_on(event.channel, note, vel),
+                MidiMessage::NoteOff(note, _) => self.handle_note_off(note),
+                MidiMessage::SustainPedal(on) => self.set_pedal(on),
+                MidiMessage::ControlChange(64, value) => self.set_pedal(value >= 64),
+                _ => ()
+            }
+        }
+
+        let mut out = 0.0;
+        for voice in self.voices.iter_mut() {
+            if !voice.active {
+                continue;
+            }
+            let sample = &self.samples[voice.sample];
+
+            // Linear-interpolated read from the native sample data.
+            let idx = voice.pos.floor() as usize;
+            let s = if idx + 1 < sample.data.len() {
+                let frac = voice.pos - voice.pos.floor();
+                sample.data[idx]*(1.0-frac) + sample.data[idx+1]*frac
+            } else {
+                0.0
+            };
+            out += s*voice.gain*voice.volume*self.state.gain(voice.channel);
+
+            // Advance the falloff envelope.
+            if voice.releasing {
+                voice.gain -= voice.release_rate;
+                if voice.gain <= 0.0 {
+                    voice.gain = 0.0;
+                    voice.active = false;
+                }
+            } else if voice.gain < 1.0 {
+                voice.gain = (voice.gain + voice.attack_rate).min(1.0);
+            }
+
+            // Advance the read position, bending the playback rate by the
+            // channel's current pitch bend and wrapping the sustain loop.
+            let rate = voice.rate * 2.0.powf(self.state.bend(voice.channel)/1200.0);
+            voice.pos += rate;
+            if sample.loop_end > sample.loop_start &&
+                    voice.pos >= sample.loop_end as f32 {
+                voice.pos -= (sample.loop_end - sample.loop_start) as f32;
+            }
+            if voice.pos as usize >= sample.data.len() {
+                voice.active = false;
+            }
+        }
+
+        // Reclaim any released voices that have fully decayed to silence.
+        self.voices.free_silent(|v| !v.active);
+        outputs[0] = out;
+    }
+}
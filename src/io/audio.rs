@@ -1,158 +1,332 @@
 //! Provides audio IO from OS sound devices.
+//!
+//! The backend is built on cpal, a pure-Rust cross-platform audio library
+//! (WASAPI on Windows, ALSA on Linux, CoreAudio on macOS), so the crate no
+//! longer depends on a C PortAudio build. Rather than blocking inside a busy
+//! `tick` loop, each stream is driven by a data-request callback the host
+//! invokes whenever the device needs (or has produced) samples; the callback
+//! exchanges frames with the signal chain through a
+//! [`CircularBuffer`](struct.CircularBuffer.html) shared behind a mutex, which
+//! both the callback and `tick` lock while they read or write it.
+//!
+//! An [`AudioEngine`](struct.AudioEngine.html) enumerates devices and
+//! negotiates a supported stream configuration instead of assuming 44100 Hz
+//! stereo float. Each opened stream returns a handle that can be played,
+//! paused, and destroyed independently.
 
-extern crate portaudio;
+extern crate cpal;
 
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use self::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
-use types::{SAMPLE_RATE, Device, Sample, Time};
-use components::{InputArray, OutputArray};
+use types::{SAMPLE_RATE, AudioDevice, Sample, Time};
 
 
-/// Defines the audio format for Portaudio.
-static PORTAUDIO_T: portaudio::pa::SampleFormat =
-    portaudio::pa::SampleFormat::Float32;
-
-/// Defines the buffer size for Portaudio
+/// The default number of frames to buffer between the chain and the device.
 static BUFFER_SIZE: usize = 256;
 
 
-/// Used to handle portaudio resources.
-pub struct AudioEngine;
+/// A single-producer, single-consumer ring buffer.
+///
+/// `insert` writes a value only when there is room, dropping it on overflow
+/// rather than blocking; the drain stops as soon as the buffer is empty. The
+/// `inp` and `out` indices chase each other around a backing store one element
+/// larger than the requested capacity, so a full buffer is distinguishable
+/// from an empty one.
+pub struct CircularBuffer<T> {
+    buffer: Vec<T>,
+    inp: usize,
+    out: usize,
+}
+
+impl<T: Clone + Default> CircularBuffer<T> {
+    /// Returns an empty ring that can hold up to `capacity` elements.
+    pub fn new(capacity: usize) -> CircularBuffer<T> {
+        CircularBuffer {
+            buffer: vec![T::default(); capacity + 1],
+            inp: 0,
+            out: 0,
+        }
+    }
+
+    /// Returns the index that `inp` would advance to.
+    fn next_in(&self) -> usize {
+        (self.inp + 1) % self.buffer.len()
+    }
+
+    /// Attempts to push `value`. Returns `false` if the buffer was full and the
+    /// value was dropped.
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.next_in() != self.out {
+            self.buffer[self.inp] = value;
+            self.inp = self.next_in();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Pops the oldest value, or `None` if the buffer is empty.
+    pub fn drain(&mut self) -> Option<T> {
+        if self.out == self.inp {
+            None
+        } else {
+            let value = self.buffer[self.out].clone();
+            self.out = (self.out + 1) % self.buffer.len();
+            Some(value)
+        }
+    }
+
+    /// Returns the number of elements currently buffered.
+    pub fn fill(&self) -> usize {
+        (self.inp + self.buffer.len() - self.out) % self.buffer.len()
+    }
+}
+
+type SharedRing = Arc<Mutex<CircularBuffer<Sample>>>;
+
+
+/// Enumerates devices and opens audio streams.
+pub struct AudioEngine {
+    host: cpal::Host,
+    buffer_size: usize,
+}
 
 impl AudioEngine {
+    /// Opens the default audio host with the default buffer size.
     pub fn open() -> Result<AudioEngine, &'static str> {
-        if portaudio::pa::initialize().is_err() {
-            return Result::Err("failed to initialize portaudio");
+        AudioEngine::with_buffer_size(BUFFER_SIZE)
+    }
+
+    /// Opens the default audio host, buffering `buffer_size` frames between the
+    /// chain clock and the device callback.
+    pub fn with_buffer_size(buffer_size: usize) -> Result<AudioEngine, &'static str> {
+        Ok(AudioEngine {
+            host: cpal::default_host(),
+            buffer_size: buffer_size,
+        })
+    }
+
+    /// Opens the default output device for `num_channels` channels.
+    pub fn default_output(&self, num_channels: usize) -> Result<AudioOut, &'static str> {
+        let device = try!(self.host.default_output_device()
+            .ok_or("no default output device"));
+        let (config, format) = try!(negotiate(&device, num_channels, true));
+        let channels = config.channels as usize;
+        let ring: SharedRing =
+            Arc::new(Mutex::new(CircularBuffer::new(channels*self.buffer_size)));
+
+        // The device dictates its own sample format, so build a stream of the
+        // matching type and convert our f32 frames into it.
+        let stream = try!(match format {
+            cpal::SampleFormat::F32 => build_output::<f32>(&device, &config, ring.clone()),
+            cpal::SampleFormat::I16 => build_output::<i16>(&device, &config, ring.clone()),
+            cpal::SampleFormat::U16 => build_output::<u16>(&device, &config, ring.clone()),
+            _ => Err("unsupported output sample format"),
+        });
+        try!(stream.play().map_err(|_| "failed to start output stream"));
+
+        Ok(AudioOut {
+            ring: ring,
+            num_channels: channels,
+            stream: StreamHandle::new(stream),
+        })
+    }
+
+    /// Opens the default input device for `num_channels` channels.
+    pub fn default_input(&self, num_channels: usize) -> Result<AudioIn, &'static str> {
+        let device = try!(self.host.default_input_device()
+            .ok_or("no default input device"));
+        let (config, format) = try!(negotiate(&device, num_channels, false));
+        let channels = config.channels as usize;
+        let ring: SharedRing =
+            Arc::new(Mutex::new(CircularBuffer::new(channels*self.buffer_size)));
+
+        // Convert the device's native sample format into our f32 frames.
+        let stream = try!(match format {
+            cpal::SampleFormat::F32 => build_input::<f32>(&device, &config, ring.clone()),
+            cpal::SampleFormat::I16 => build_input::<i16>(&device, &config, ring.clone()),
+            cpal::SampleFormat::U16 => build_input::<u16>(&device, &config, ring.clone()),
+            _ => Err("unsupported input sample format"),
+        });
+        try!(stream.play().map_err(|_| "failed to start input stream"));
+
+        Ok(AudioIn {
+            ring: ring,
+            num_channels: channels,
+            stream: StreamHandle::new(stream),
+        })
+    }
+}
+
+/// Negotiates a stream config and sample format against the device's supported
+/// configurations.
+///
+/// Preference goes to a config offering `num_channels` at the crate's
+/// `SAMPLE_RATE`; failing that, the device's own default config (with its
+/// native channel count, rate, and sample format) is used rather than forcing
+/// an unsupported layout.
+fn negotiate(device: &cpal::Device, num_channels: usize, output: bool)
+        -> Result<(cpal::StreamConfig, cpal::SampleFormat), &'static str> {
+    let target = cpal::SampleRate(SAMPLE_RATE);
+
+    // First look for a supported range with the requested channel count that
+    // spans our sample rate.
+    let supported = if output {
+        device.supported_output_configs().ok()
+    } else {
+        device.supported_input_configs().ok()
+    };
+    if let Some(ranges) = supported {
+        for range in ranges {
+            if range.channels() as usize == num_channels &&
+                    range.min_sample_rate() <= target &&
+                    target <= range.max_sample_rate() {
+                let config = range.with_sample_rate(target);
+                return Ok((config.config(), config.sample_format()));
+            }
         }
-        Result::Ok(AudioEngine)
     }
+
+    // Otherwise honor whatever the device reports as its default.
+    let default = if output {
+        try!(device.default_output_config().map_err(|_| "no supported output config"))
+    } else {
+        try!(device.default_input_config().map_err(|_| "no supported input config"))
+    };
+    Ok((default.config(), default.sample_format()))
+}
+
+/// Builds an output stream of sample type `T`, draining f32 frames from `ring`
+/// and converting them to the device format.
+fn build_output<T>(device: &cpal::Device, config: &cpal::StreamConfig, ring: SharedRing)
+        -> Result<cpal::Stream, &'static str>
+        where T: cpal::Sample {
+    device.build_output_stream(config,
+        move |data: &mut [T], _| {
+            let mut ring = ring.lock().unwrap();
+            for s in data.iter_mut() {
+                // Pull whatever the chain has queued, emitting silence on
+                // underrun rather than blocking.
+                *s = cpal::Sample::from::<f32>(&ring.drain().unwrap_or(0.0));
+            }
+        },
+        |_| {}
+    ).map_err(|_| "failed to open output stream")
+}
+
+/// Builds an input stream of sample type `T`, converting captured frames to f32
+/// and pushing them into `ring`.
+fn build_input<T>(device: &cpal::Device, config: &cpal::StreamConfig, ring: SharedRing)
+        -> Result<cpal::Stream, &'static str>
+        where T: cpal::Sample {
+    device.build_input_stream(config,
+        move |data: &[T], _| {
+            let mut ring = ring.lock().unwrap();
+            for &s in data {
+                // Push captured frames, dropping on overflow.
+                ring.insert(s.to_f32());
+            }
+        },
+        |_| {}
+    ).map_err(|_| "failed to open input stream")
+}
+
+
+/// A handle that plays, pauses, and destroys an open stream.
+///
+/// The stream keeps running until the handle is dropped.
+pub struct StreamHandle {
+    stream: cpal::Stream,
 }
 
-impl Drop for AudioEngine {
-    fn drop(&mut self)
-    {
-        assert!(portaudio::pa::terminate().is_ok());
+impl StreamHandle {
+    fn new(stream: cpal::Stream) -> StreamHandle {
+        StreamHandle { stream: stream }
+    }
+
+    /// Resumes the stream callback.
+    pub fn play(&self) {
+        let _ = self.stream.play();
+    }
+
+    /// Pauses the stream callback, if the host supports it.
+    pub fn pause(&self) {
+        let _ = self.stream.pause();
     }
 }
 
 
 /// Reads audio from the OS's default input device.
 pub struct AudioIn {
-    /// Output audio channels
-    pub outputs: OutputArray<Sample>,
-
-    #[allow(dead_code)] // the engine is used as an RAII marker
-    engine: Rc<AudioEngine>,
-    pa_stream: portaudio::pa::Stream<Sample, Sample>,
+    ring: SharedRing,
     num_channels: usize,
-    buffer: Vec<Sample>,
-    samples_read: usize,
+    stream: StreamHandle,
 }
 
 impl AudioIn {
-    /// Opens an audio input stream reading `num_channels` inputs.
-    pub fn new(engine: Rc<AudioEngine>, num_channels: usize) -> AudioIn {
-        // Open a stream
-        let mut pa_stream = portaudio::pa::Stream::new();
-        assert!(pa_stream.open_default(SAMPLE_RATE as f64, BUFFER_SIZE as u32,
-                                       num_channels as i32, 0i32,
-                                       PORTAUDIO_T).is_ok());
-        assert!(pa_stream.start().is_ok());
-
-        AudioIn {
-            outputs: OutputArray::new(num_channels),
-            engine: engine,
-            pa_stream: pa_stream,
-            num_channels: num_channels,
-            buffer: Vec::with_capacity(num_channels*BUFFER_SIZE),
-            samples_read: BUFFER_SIZE,
-        }
+    /// Returns the number of captured samples waiting to be consumed.
+    pub fn fill(&self) -> usize {
+        self.ring.lock().unwrap().fill()
     }
-}
 
-impl Drop for AudioIn {
-    fn drop(&mut self) {
-        assert!(self.pa_stream.stop().is_ok());
-        assert!(self.pa_stream.close().is_ok());
+    /// Returns a reference to this stream's playback handle.
+    pub fn stream(&self) -> &StreamHandle {
+        &self.stream
     }
 }
 
-impl Device for AudioIn {
-    fn tick(&mut self, _t: Time) {
-        if self.samples_read == BUFFER_SIZE {
-            let result = self.pa_stream.read(BUFFER_SIZE as u32);
-            match result {
-                Ok(v) => self.buffer = v.clone(),
-                Err(e) => panic!(e)
-            }
-            self.samples_read = 0;
-        }
+impl AudioDevice for AudioIn {
+    fn num_inputs(&self) -> usize {
+        0
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.num_channels
+    }
 
-        for i in (0 .. self.num_channels) {
-            let s = self.buffer[self.samples_read*self.num_channels + i];
-            self.outputs.push(i, s);
+    fn tick(&mut self, _: Time, _: &[Sample], outputs: &mut[Sample]) {
+        let mut ring = self.ring.lock().unwrap();
+        for o in outputs.iter_mut() {
+            *o = ring.drain().unwrap_or(0.0);
         }
-        self.samples_read += 1;
     }
 }
 
 
 /// Writes audio to the OS's default output device.
 pub struct AudioOut {
-    /// Input audio channels
-    pub inputs: InputArray<Sample>,
-
-    #[allow(dead_code)] // the engine is used as an RAII marker
-    engine: Rc<AudioEngine>,
-    pa_stream: portaudio::pa::Stream<Sample, Sample>,
+    ring: SharedRing,
     num_channels: usize,
-    buffer: Vec<Sample>,
-    samples_written: usize,
+    stream: StreamHandle,
 }
 
 impl AudioOut {
-    /// Opens an output stream writing `num_channels` outputs.
-    pub fn new(engine: Rc<AudioEngine>, num_channels: usize) -> AudioOut {
-        // Open a stream
-        let mut pa_stream = portaudio::pa::Stream::new();
-        assert!(pa_stream.open_default(SAMPLE_RATE as f64, BUFFER_SIZE as u32,
-                                       0i32, num_channels as i32,
-                                       PORTAUDIO_T).is_ok());
-        assert!(pa_stream.start().is_ok());
-
-        AudioOut {
-            inputs: InputArray::new(num_channels),
-            engine: engine,
-            pa_stream: pa_stream,
-            num_channels: num_channels,
-            buffer: Vec::with_capacity(num_channels*BUFFER_SIZE),
-            samples_written: 0,
-        }
+    /// Returns the number of samples queued but not yet played.
+    pub fn fill(&self) -> usize {
+        self.ring.lock().unwrap().fill()
     }
-}
 
-impl Drop for AudioOut {
-    fn drop(&mut self) {
-        assert!(self.pa_stream.stop().is_ok());
-        assert!(self.pa_stream.close().is_ok());
+    /// Returns a reference to this stream's playback handle.
+    pub fn stream(&self) -> &StreamHandle {
+        &self.stream
     }
 }
 
-impl Device for AudioOut {
-    fn tick(&mut self, t: Time) {
-        for i in (0 .. self.num_channels) {
-            let mut s = self.inputs.get(i, t).unwrap_or(0.0);
-            if s > 1.0 { s = 1.0; }
-            if s < -1.0 { s = -1.0; }
-            self.buffer.push(s)
-        }
-        self.samples_written += 1;
+impl AudioDevice for AudioOut {
+    fn num_inputs(&self) -> usize {
+        self.num_channels
+    }
+
+    fn num_outputs(&self) -> usize {
+        0
+    }
 
-        if self.samples_written == BUFFER_SIZE {
-            assert!(self.pa_stream.write(self.buffer.clone(),
-                                         BUFFER_SIZE as u32).is_ok());
-            self.samples_written = 0;
-            self.buffer.clear()
+    fn tick(&mut self, _: Time, inputs: &[Sample], _: &mut[Sample]) {
+        let mut ring = self.ring.lock().unwrap();
+        for &s in inputs {
+            let s = if s > 1.0 { 1.0 } else if s < -1.0 { -1.0 } else { s };
+            ring.insert(s);
         }
     }
 }
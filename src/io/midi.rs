@@ -1,97 +1,127 @@
 //! Provides MIDI input from OS MIDI devices.
-
-#![experimental]
+//!
+//! A [`MidiEngine`](struct.MidiEngine.html) owns the PortMidi context and
+//! enumerates the available input ports. Callers may list the ports with their
+//! names, open the system default, or open a specific port by index. This
+//! mirrors how a user picks a controller at startup, and is needed on any
+//! machine with more than one MIDI interface.
 
 extern crate portmidi;
 
-use std::vec::Vec;
+use types::{MidiDevice, MidiEvent, MidiMessage, Time};
+
+
+/// Defines the maximum event buffer size for portmidi.
+const BUFFER_SIZE: usize = 256;
+
+
+/// Describes an available MIDI input port.
+#[derive(Clone, Debug)]
+pub struct MidiDeviceInfo {
+    /// The PortMidi device index, used with `MidiIn::with_device`.
+    pub index: usize,
+    /// The human-readable port name reported by the OS.
+    pub name: String,
+}
+
 
-use core::types::{Device, MidiEvent, MidiMessage, Time};
-use core::components::OutputElement;
-use core::init;
+/// Owns the PortMidi context and enumerates MIDI input ports.
+pub struct MidiEngine {
+    context: portmidi::PortMidi,
+}
 
+impl MidiEngine {
+    /// Initializes the MIDI subsystem.
+    pub fn open() -> Result<MidiEngine, &'static str> {
+        let context = try!(portmidi::PortMidi::new()
+            .map_err(|_| "failed to initialize portmidi"));
+        Ok(MidiEngine { context: context })
+    }
 
-/// Defines the maximum event buffer size for portmidi
-static BUFFER_SIZE: int = 256;
+    /// Lists the available MIDI input ports with their names.
+    pub fn inputs(&self) -> Vec<MidiDeviceInfo> {
+        self.context.devices().unwrap_or(Vec::new()).into_iter()
+            .filter(|d| d.is_input())
+            .map(|d| MidiDeviceInfo { index: d.id() as usize, name: d.name().clone() })
+            .collect()
+    }
 
+    /// Returns the index of the system default input port, if any.
+    pub fn default_input(&self) -> Option<usize> {
+        self.context.default_input_device_id().ok().map(|id| id as usize)
+    }
+
+    /// Opens the system default input port.
+    pub fn choose_input(&self) -> Result<MidiIn, &'static str> {
+        MidiIn::new(self)
+    }
+}
 
-/// Converts a raw portmidi message to an oxcable MIDI event
-fn midievent_from_portmidi(event: portmidi::midi::PmEvent) -> MidiEvent {
-    let msg = event.message;
+
+/// Reads MIDI events from a single OS input port.
+pub struct MidiIn {
+    pm_stream: portmidi::InputPort,
+}
+
+impl MidiIn {
+    /// Opens the system default MIDI input port.
+    pub fn new(engine: &MidiEngine) -> Result<MidiIn, &'static str> {
+        let index = try!(engine.default_input().ok_or("no default MIDI input"));
+        MidiIn::with_device(engine, index)
+    }
+
+    /// Opens the MIDI input port with the given device index.
+    pub fn with_device(engine: &MidiEngine, index: usize) -> Result<MidiIn, &'static str> {
+        let info = try!(engine.context.device(index as i32)
+            .map_err(|_| "no such MIDI device"));
+        let pm_stream = try!(engine.context.input_port(info, BUFFER_SIZE)
+            .map_err(|_| "failed to open MIDI input port"));
+        Ok(MidiIn { pm_stream: pm_stream })
+    }
+}
+
+impl MidiDevice for MidiIn {
+    fn get_events(&mut self, t: Time) -> Vec<MidiEvent> {
+        let mut events = Vec::new();
+        while let Ok(Some(messages)) = self.pm_stream.read_n(BUFFER_SIZE) {
+            for message in messages {
+                events.push(midievent_from_portmidi(message.message, t));
+            }
+        }
+        events
+    }
+}
+
+
+/// Converts a raw portmidi message to an oxcable MIDI event.
+fn midievent_from_portmidi(msg: portmidi::MidiMessage, t: Time) -> MidiEvent {
     let channel = (msg.status & 0x0F) as u8;
-    let payload = match (msg.status as u8) >> 4 {
+    let payload = match msg.status >> 4 {
         0b1000 => {
-            let note = msg.data1 as u8;
+            let note = msg.data1;
             let velocity = (msg.data2 as f32) / 127.0;
             MidiMessage::NoteOff(note, velocity)
         },
         0b1001 => {
-            let note = msg.data1 as u8;
+            let note = msg.data1;
             let velocity = (msg.data2 as f32) / 127.0;
             MidiMessage::NoteOn(note, velocity)
-        }
+        },
         0b1110 => {
-            let int_value = (msg.data2 as i16 << 7) | (msg.data1 as i16);
-            let bend = (int_value - 0x2000) as f32 / 
-                (0x2000i16) as f32;
+            let int_value = ((msg.data2 as i16) << 7) | (msg.data1 as i16);
+            let bend = (int_value - 0x2000) as f32 / (0x2000i16) as f32;
             MidiMessage::PitchBend(bend)
-        }
+        },
         0b1010 => {
-            let note = msg.data1 as u8;
+            let note = msg.data1;
             let pressure = (msg.data2 as f32) / 127.0;
             MidiMessage::KeyPressure(note, pressure)
-        }
-        0b1011 => MidiMessage::ControlChange(msg.data1 as u8, msg.data2 as u8),
-        0b1100 => MidiMessage::ProgramChange(msg.data1 as u8),
+        },
+        0b1011 => MidiMessage::ControlChange(msg.data1, msg.data2),
+        0b1100 => MidiMessage::ProgramChange(msg.data1),
         0b1101 => MidiMessage::ChannelPressure(msg.data1 as f32 / 127.0),
-        _ => MidiMessage::Other(msg.status as u8, msg.data1 as u8, 
-                                msg.data2 as u8)
+        _ => MidiMessage::Other(msg.status, msg.data1, msg.data2)
     };
 
-    MidiEvent { channel: channel, payload: payload }
-}
-
-
-/// Reads audio from the OS's default midi device.
-pub struct MidiIn {
-    /// Output midi channel
-    pub output: OutputElement<Vec<MidiEvent>>,
-
-    pm_stream: portmidi::midi::PmInputPort,
+    MidiEvent { channel: channel, time: t, payload: payload }
 }
-
-impl MidiIn {
-    /// Opens a midi input stream.
-    pub fn new() -> MidiIn {
-        // Check for initialization
-        if !init::is_initialized() {
-            panic!("Must initialize oxcable first");
-        }
-        
-        // Open a stream. For now, use firs device
-        let mut pm_stream = portmidi::midi::PmInputPort::new(1, BUFFER_SIZE);
-        assert_eq!(pm_stream.open(), portmidi::midi::PmError::PmNoError);
-
-        MidiIn {
-            output: OutputElement::new(),
-            pm_stream: pm_stream,
-        }
-    }
-
-    /// Closes the portmidi stream
-    pub fn stop(&mut self) {
-        assert_eq!(self.pm_stream.close(), portmidi::midi::PmError::PmNoError);
-    }
-}
-
-impl Device for MidiIn {
-    fn tick(&mut self, _t: Time) {
-        let mut events = Vec::new();
-        while self.pm_stream.poll() == portmidi::midi::PmError::PmGotData {
-            let pm_message = self.pm_stream.read().unwrap();
-            let event = midievent_from_portmidi(pm_message);
-            events.push(event);
-        }
-        self.output.push(events);
-    }
-}
\ No newline at end of file
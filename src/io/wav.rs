@@ -0,0 +1,178 @@
+//! Writing audio out to WAV files.
+//!
+//! The writer converts the crate's internal `f32` `Sample` stream to a chosen
+//! on-disk [`SampleFormat`](enum.SampleFormat.html) as it writes, covering the
+//! common integer depths plus IEEE float. The conversions scale and clamp to
+//! each type's range, round to nearest, and apply the midpoint offset unsigned
+//! 8-bit PCM expects.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use oxcable::io::wav::{SampleFormat, WavWriter};
+//! // 16-bit PCM, the default:
+//! let wav = WavWriter::new("out.wav", 2);
+//! // ...or pick another format explicitly:
+//! let wav = WavWriter::with_format("out.wav", 2, SampleFormat::Float32);
+//! ```
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use types::{SAMPLE_RATE, AudioDevice, Sample, Time};
+
+
+/// The on-disk encoding of each sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleFormat {
+    /// Unsigned 8-bit integer PCM.
+    Unsigned8,
+    /// Signed 16-bit integer PCM.
+    Signed16,
+    /// Signed 24-bit integer PCM.
+    Signed24,
+    /// Signed 32-bit integer PCM.
+    Signed32,
+    /// 32-bit IEEE floating point.
+    Float32,
+}
+
+impl SampleFormat {
+    /// Returns the number of bits used per sample.
+    pub fn bits(&self) -> u16 {
+        match *self {
+            SampleFormat::Unsigned8 => 8,
+            SampleFormat::Signed16 => 16,
+            SampleFormat::Signed24 => 24,
+            SampleFormat::Signed32 => 32,
+            SampleFormat::Float32 => 32,
+        }
+    }
+
+    /// Returns the number of bytes used per sample.
+    pub fn bytes(&self) -> usize {
+        (self.bits() / 8) as usize
+    }
+
+    /// Returns the WAVE format tag (1 for integer PCM, 3 for IEEE float).
+    pub fn tag(&self) -> u16 {
+        match *self {
+            SampleFormat::Float32 => 3,
+            _ => 1,
+        }
+    }
+
+    /// Converts a float `Sample` into this format and writes it little-endian.
+    fn write<W: Write>(&self, out: &mut W, s: Sample) -> ::std::io::Result<()> {
+        match *self {
+            SampleFormat::Unsigned8 => {
+                let v = (clamp(s)*127.0).round() as i32 + 128;
+                out.write_u8(v as u8)
+            },
+            SampleFormat::Signed16 => {
+                out.write_i16::<LittleEndian>((clamp(s)*32767.0).round() as i16)
+            },
+            SampleFormat::Signed24 => {
+                let v = (clamp(s)*8_388_607.0).round() as i32;
+                try!(out.write_u8((v & 0xFF) as u8));
+                try!(out.write_u8(((v >> 8) & 0xFF) as u8));
+                out.write_u8(((v >> 16) & 0xFF) as u8)
+            },
+            SampleFormat::Signed32 => {
+                out.write_i32::<LittleEndian>((clamp(s) as f64 * 2_147_483_647.0).round() as i32)
+            },
+            SampleFormat::Float32 => {
+                out.write_f32::<LittleEndian>(s)
+            },
+        }
+    }
+}
+
+/// Clamps a sample into the `[-1.0, 1.0]` range before integer scaling.
+fn clamp(s: Sample) -> Sample {
+    if s > 1.0 { 1.0 } else if s < -1.0 { -1.0 } else { s }
+}
+
+
+/// Writes its input channels to a WAV file.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    format: SampleFormat,
+    num_channels: usize,
+    samples_written: usize,
+}
+
+impl WavWriter {
+    /// Opens `filename` for writing `num_channels` channels of 16-bit PCM.
+    pub fn new(filename: &str, num_channels: usize) -> WavWriter {
+        WavWriter::with_format(filename, num_channels, SampleFormat::Signed16)
+    }
+
+    /// Opens `filename` for writing `num_channels` channels in `format`.
+    pub fn with_format(filename: &str, num_channels: usize,
+                       format: SampleFormat) -> WavWriter {
+        let file = File::create(filename).unwrap();
+        let mut writer = BufWriter::new(file);
+        write_header(&mut writer, num_channels, format);
+        WavWriter {
+            writer: writer,
+            format: format,
+            num_channels: num_channels,
+            samples_written: 0,
+        }
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        // Backfill the RIFF and data chunk sizes now that the length is known.
+        let data_bytes = self.samples_written*self.format.bytes();
+        let _ = self.writer.flush();
+        let _ = self.writer.seek(SeekFrom::Start(4));
+        let _ = self.writer.write_u32::<LittleEndian>((36 + data_bytes) as u32);
+        let _ = self.writer.seek(SeekFrom::Start(40));
+        let _ = self.writer.write_u32::<LittleEndian>(data_bytes as u32);
+        let _ = self.writer.flush();
+    }
+}
+
+impl AudioDevice for WavWriter {
+    fn num_inputs(&self) -> usize {
+        self.num_channels
+    }
+
+    fn num_outputs(&self) -> usize {
+        0
+    }
+
+    fn tick(&mut self, _: Time, inputs: &[Sample], _: &mut[Sample]) {
+        for &s in inputs {
+            self.format.write(&mut self.writer, s).unwrap();
+            self.samples_written += 1;
+        }
+    }
+}
+
+
+/// Writes the 44-byte canonical WAV header, leaving the sizes as placeholders.
+fn write_header<W: Write>(out: &mut W, num_channels: usize, format: SampleFormat) {
+    let block_align = num_channels*format.bytes();
+    let byte_rate = SAMPLE_RATE as usize*block_align;
+
+    out.write_all(b"RIFF").unwrap();
+    out.write_u32::<LittleEndian>(0).unwrap(); // file size - 8, filled on drop
+    out.write_all(b"WAVE").unwrap();
+
+    out.write_all(b"fmt ").unwrap();
+    out.write_u32::<LittleEndian>(16).unwrap();
+    out.write_u16::<LittleEndian>(format.tag()).unwrap();
+    out.write_u16::<LittleEndian>(num_channels as u16).unwrap();
+    out.write_u32::<LittleEndian>(SAMPLE_RATE).unwrap();
+    out.write_u32::<LittleEndian>(byte_rate as u32).unwrap();
+    out.write_u16::<LittleEndian>(block_align as u16).unwrap();
+    out.write_u16::<LittleEndian>(format.bits()).unwrap();
+
+    out.write_all(b"data").unwrap();
+    out.write_u32::<LittleEndian>(0).unwrap(); // data size, filled on drop
+}
@@ -0,0 +1,172 @@
+//! Channel count conversion.
+//!
+//! Many devices assume a fixed channel layout, which makes it awkward to wire
+//! together a mono source and a stereo sink, or to fold a surround mix down to
+//! two channels. The `ChannelConverter` bridges two devices with mismatched
+//! channel counts by mapping `num_inputs` input channels to `num_outputs`
+//! output channels using a configurable operation.
+//!
+//! ## Example
+//!
+//! To downmix a stereo signal to mono inside a `DeviceChain`:
+//!
+//! ```
+//! use oxcable::mixers::convert::ChannelConverter;
+//! let downmix = ChannelConverter::stereo_to_mono();
+//! ```
+
+use types::{AudioDevice, Sample, Time};
+
+
+/// The operation used to map input channels to output channels.
+#[derive(Clone, Debug)]
+pub enum ConvertOp {
+    /// Copies the inputs directly to the outputs. The channel counts must be
+    /// equal.
+    Passthrough,
+    /// Permutes or selects source channels into destination slots. Output `o`
+    /// reads from input `map[o]`.
+    Reorder(Vec<usize>),
+    /// Broadcasts a single mono input to every output channel.
+    DupMono,
+    /// Applies a `num_outputs` by `num_inputs` coefficient matrix per frame, so
+    /// that `out[o] = Σ_i matrix[o*num_inputs + i] * in[i]`.
+    Remix(Vec<f32>),
+}
+pub use self::ConvertOp::*;
+
+
+/// A device that converts between channel counts.
+pub struct ChannelConverter {
+    op: ConvertOp,
+    num_inputs: usize,
+    num_outputs: usize,
+}
+
+impl ChannelConverter {
+    /// Returns a converter mapping `num_inputs` channels to `num_outputs`
+    /// channels using the provided operation.
+    pub fn new(op: ConvertOp, num_inputs: usize, num_outputs: usize) -> Self {
+        match op {
+            Passthrough => assert_eq!(num_inputs, num_outputs),
+            Reorder(ref map) => assert_eq!(map.len(), num_outputs),
+            DupMono => assert_eq!(num_inputs, 1),
+            Remix(ref matrix) => assert_eq!(matrix.len(), num_inputs*num_outputs),
+        }
+        ChannelConverter {
+            op: op,
+            num_inputs: num_inputs,
+            num_outputs: num_outputs,
+        }
+    }
+
+    /// Returns a converter that downmixes a stereo signal to mono by averaging
+    /// the two channels.
+    pub fn stereo_to_mono() -> Self {
+        ChannelConverter::new(Remix(vec![0.5, 0.5]), 2, 1)
+    }
+
+    /// Returns a converter that broadcasts a mono signal to both stereo
+    /// channels.
+    pub fn mono_to_stereo() -> Self {
+        ChannelConverter::new(DupMono, 1, 2)
+    }
+
+    /// Returns a converter that folds a 5.1 surround mix down to stereo.
+    ///
+    /// The input channels are ordered front left, front right, center, LFE,
+    /// surround left, surround right. The center and surround channels are
+    /// scaled by `1/√2` before being summed into the front channels, and the
+    /// LFE channel is dropped.
+    pub fn surround_51_to_stereo() -> Self {
+        let a = 1.0 / 2.0f32.sqrt();
+        ChannelConverter::new(Remix(vec![
+            //  FL   FR   C    LFE  SL   SR
+            1.0, 0.0, a,   0.0, a,   0.0,
+            0.0, 1.0, a,   0.0, 0.0, a,
+        ]), 6, 2)
+    }
+}
+
+impl AudioDevice for ChannelConverter {
+    fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.num_outputs
+    }
+
+    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
+        match self.op {
+            Passthrough => {
+                for (o, i) in outputs.iter_mut().zip(inputs) {
+                    *o = *i;
+                }
+            },
+            Reorder(ref map) => {
+                for (o, &src) in outputs.iter_mut().zip(map) {
+                    *o = inputs[src];
+                }
+            },
+            DupMono => {
+                for o in outputs.iter_mut() {
+                    *o = inputs[0];
+                }
+            },
+            Remix(ref matrix) => {
+                for o in 0..self.num_outputs {
+                    let mut s = 0.0;
+                    for i in 0..self.num_inputs {
+                        s += matrix[o*self.num_inputs + i] * inputs[i];
+                    }
+                    outputs[o] = s;
+                }
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use testing::flt_eq;
+    use types::{AudioDevice, Time};
+    use super::{ChannelConverter, Reorder};
+
+    #[test]
+    fn test_stereo_to_mono() {
+        let mut conv = ChannelConverter::stereo_to_mono();
+        let mut out = [0.0];
+        conv.tick(0 as Time, &[1.0, -0.5], &mut out);
+        assert!(flt_eq(out[0], 0.25));
+    }
+
+    #[test]
+    fn test_mono_to_stereo() {
+        let mut conv = ChannelConverter::mono_to_stereo();
+        let mut out = [0.0, 0.0];
+        conv.tick(0 as Time, &[0.7], &mut out);
+        assert!(flt_eq(out[0], 0.7));
+        assert!(flt_eq(out[1], 0.7));
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut conv = ChannelConverter::new(Reorder(vec![1, 0, 1]), 2, 3);
+        let mut out = [0.0; 3];
+        conv.tick(0 as Time, &[-1.0, 1.0], &mut out);
+        assert!(flt_eq(out[0], 1.0));
+        assert!(flt_eq(out[1], -1.0));
+        assert!(flt_eq(out[2], 1.0));
+    }
+
+    #[test]
+    fn test_surround_downmix() {
+        let mut conv = ChannelConverter::surround_51_to_stereo();
+        let mut out = [0.0, 0.0];
+        conv.tick(0 as Time, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0], &mut out);
+        assert!(flt_eq(out[0], 1.0));
+        assert!(flt_eq(out[1], 0.0));
+    }
+}
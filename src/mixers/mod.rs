@@ -3,11 +3,13 @@
 #![experimental]
 
 pub use self::adder::Adder;
+pub use self::convert::ChannelConverter;
 pub use self::gain::Gain;
 pub use self::multiplier::Multiplier;
 pub use self::multiplexer::Multiplexer;
 
 pub mod adder;
+pub mod convert;
 pub mod gain;
 pub mod multiplexer;
 pub mod multiplier;
@@ -41,6 +41,7 @@ use num::traits::Float;
 use rand::random;
 
 use types::{SAMPLE_RATE, AudioDevice, MessageReceiver, Sample, Time};
+use tween::Tween;
 
 
 /// Defines the messages that the Oscillator supports.
@@ -60,6 +61,29 @@ pub enum Message {
 pub use self::Message::*;
 
 
+/// Shift register width for the PSG noise generator.
+#[derive(Clone, Copy, Debug)]
+pub enum Width {
+    /// A 7-bit register, producing a short, periodic pattern.
+    Width7,
+    /// A 15-bit register, producing long, white-sounding noise.
+    Width15
+}
+pub use self::Width::*;
+
+
+/// Selects how the `Sine` waveform is evaluated.
+#[derive(Clone, Copy, Debug)]
+pub enum SinMode {
+    /// Evaluate with the exact `f32::sin`.
+    Exact,
+    /// Evaluate from the precomputed lookup table: faster for dense graphs, at
+    /// the cost of a small interpolation error.
+    Fast
+}
+pub use self::SinMode::*;
+
+
 /// Antialiasing method for certain waveforms.
 #[derive(Clone, Copy, Debug)]
 pub enum AntialiasType {
@@ -74,8 +98,8 @@ pub use self::AntialiasType::*;
 /// Oscillator waveforms.
 #[derive(Clone, Copy, Debug)]
 pub enum Waveform {
-    /// A sine wave.
-    Sine,
+    /// A sine wave, evaluated either exactly or from the lookup table.
+    Sine(SinMode),
     /// A saw wave.
     Saw(AntialiasType),
     /// A square wave.
@@ -85,7 +109,14 @@ pub enum Waveform {
     /// Pure white noise.
     WhiteNoise,
     /// A series of impulses.
-    PulseTrain
+    PulseTrain,
+    /// A Game Boy-style LFSR noise channel, clocked at the oscillator
+    /// frequency. The `Width` selects the 15-bit (long) or 7-bit (short)
+    /// register period.
+    Lfsr(Width),
+    /// A Game Boy-style wave channel stepping through a 32-entry, 4-bit wave
+    /// table.
+    Wavetable([u8; 32])
 }
 pub use self::Waveform::*;
 
@@ -96,9 +127,11 @@ pub struct Oscillator {
     lfo_intensity: f32,
     transpose: f32,
     bend: f32,
+    glide_time: f32,
     phase: f32,
-    phase_delta: f32,
+    phase_delta: Tween,
     last_sample: Sample,
+    lfsr: u16,
 }
 
 impl Oscillator {
@@ -109,12 +142,24 @@ impl Oscillator {
             lfo_intensity: 0.0,
             transpose: 1.0,
             bend: 1.0,
+            glide_time: 0.0,
             phase: 0.0,
-            phase_delta: 0.0,
-            last_sample: 0.0
+            phase_delta: Tween::new(0.0),
+            last_sample: 0.0,
+            lfsr: 0xFFFF,
         }
     }
 
+    /// Sets the portamento glide time in seconds, and return the same
+    /// oscillator.
+    ///
+    /// Frequency changes then ramp over this interval instead of snapping,
+    /// avoiding clicks.
+    pub fn glide_time(mut self, glide_time: f32) -> Self {
+        self.glide_time = glide_time;
+        self
+    }
+
     /// Sets the frequency of the waveform, and return the same oscillator.
     pub fn freq(mut self, freq: f32) -> Self {
         self.handle_message(SetFreq(freq));
@@ -142,9 +187,15 @@ impl MessageReceiver for Oscillator {
     fn handle_message(&mut self, msg: Message) {
         match msg {
             SetFreq(freq) => {
-                self.phase_delta = freq*2.0*PI/(SAMPLE_RATE as f32);
+                let delta = freq*2.0*PI/(SAMPLE_RATE as f32);
+                self.phase_delta.set(delta, self.glide_time);
             },
             SetWaveform(waveform) => {
+                // Reseed the shift register so LFSR noise is deterministic from
+                // the moment the waveform is selected.
+                if let Lfsr(_) = waveform {
+                    self.lfsr = 0xFFFF;
+                }
                 self.waveform = waveform;
             },
             SetLFOIntensity(steps) => {
@@ -170,20 +221,23 @@ impl AudioDevice for Oscillator {
     }
 
     fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
-        // Tick the phase
+        // Tick the phase, gliding the base frequency toward its target.
+        let base_delta = self.phase_delta.tick();
         let phase_delta = if inputs.len() > 0 {
-            self.phase_delta*2.0.powf(inputs[0]*self.lfo_intensity)
+            base_delta*2.0.powf(inputs[0]*self.lfo_intensity)
         } else {
-            self.phase_delta
+            base_delta
         } * self.bend * self.transpose;
         self.phase += phase_delta;
-        if self.phase >= 2.0*PI {
+        let wrapped = self.phase >= 2.0*PI;
+        if wrapped {
             self.phase -= 2.0*PI;
         }
 
         // Compute the next sample
         self.last_sample = match self.waveform {
-            Sine => self.phase.sin(),
+            Sine(Exact) => self.phase.sin(),
+            Sine(Fast) => fast_sin(self.phase),
             Saw(_) => {
                 self.phase/PI -1.0 +
                     poly_blep(self.waveform, self.phase, phase_delta)
@@ -203,7 +257,26 @@ impl AudioDevice for Oscillator {
             WhiteNoise => 2.0*random::<f32>() - 1.0,
             PulseTrain => {
                 // If we wrapped around...
-                if self.phase < self.phase_delta { 1.0 } else { 0.0 }
+                if self.phase < phase_delta { 1.0 } else { 0.0 }
+            },
+            Lfsr(width) => {
+                // Clock the shift register once per phase wrap, so the
+                // oscillator frequency sets the noise rate.
+                if wrapped {
+                    let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                    self.lfsr >>= 1;
+                    self.lfsr |= bit << 14;
+                    if let Width7 = width {
+                        self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+                    }
+                }
+                if self.lfsr & 1 == 0 { 1.0 } else { -1.0 }
+            },
+            Wavetable(table) => {
+                // Step through the 4-bit wave table using the normalized phase.
+                let i = (self.phase/(2.0*PI) * 32.0) as usize % 32;
+                let nibble = (table[i] & 0x0F) as f32;
+                nibble/7.5 - 1.0
             }
         };
         outputs[0] = self.last_sample;
@@ -211,6 +284,176 @@ impl AudioDevice for Oscillator {
 }
 
 
+/// The number of entries in the cosine lookup table.
+const COS_TAB_SIZE: usize = 512;
+
+/// A precomputed cosine table spanning one full period, with a guard entry at
+/// the end so the interpolation lookup never needs a modulo.
+static mut COS_TAB: [f32; COS_TAB_SIZE + 1] = [0.0; COS_TAB_SIZE + 1];
+static mut COS_TAB_READY: bool = false;
+
+/// Fills the cosine table backing [`fast_sin`](fn.fast_sin.html) on first use.
+///
+/// This only populates the table; it never changes how any oscillator is
+/// evaluated. Whether the table or the exact `f32::sin` is used is chosen
+/// per-oscillator by the [`SinMode`](enum.SinMode.html) on the `Sine` variant.
+fn ensure_cos_tab() {
+    unsafe {
+        if COS_TAB_READY {
+            return;
+        }
+        for i in 0..COS_TAB_SIZE + 1 {
+            COS_TAB[i] = (2.0*PI * i as f32 / COS_TAB_SIZE as f32).cos();
+        }
+        COS_TAB_READY = true;
+    }
+}
+
+/// Computes a sine from the precomputed table, initializing it if needed.
+///
+/// The phase is scaled by `1/2π`, converted to a table index, and linearly
+/// interpolated between adjacent entries using the fractional part. A sine is
+/// read from the cosine table via `sin(x) = cos(x - π/2)`. Only oscillators
+/// built with `Sine(Fast)` take this path.
+pub fn fast_sin(phase: f32) -> f32 {
+    ensure_cos_tab();
+    unsafe {
+        let mut t = (phase - PI/2.0) / (2.0*PI);
+        t -= t.floor();
+        let pos = t * COS_TAB_SIZE as f32;
+        let i = pos as usize;
+        let frac = pos - i as f32;
+        COS_TAB[i]*(1.0-frac) + COS_TAB[i+1]*frac
+    }
+}
+
+
+/// Defines the messages that the WavetableOscillator supports.
+#[derive(Clone, Debug)]
+pub enum WavetableMessage {
+    /// Sets the frequency in Hz.
+    SetFreq(f32),
+    /// Replaces the single-cycle wave table.
+    SetWavetable(Vec<Sample>),
+    /// Sets the LFO vibrato depth, in steps.
+    SetLFOIntensity(f32),
+    /// Sets the pitch transposition, in steps.
+    SetTranspose(f32),
+    /// Sets the pitch bend, in steps.
+    SetBend(f32),
+}
+
+
+/// An oscillator that plays an arbitrary single-cycle waveform.
+///
+/// Unlike [`Oscillator`](struct.Oscillator.html), which offers a fixed set of
+/// classical waveforms, this device stores a user-supplied single cycle in
+/// wave table RAM and reads it back with linear interpolation. The LFO
+/// vibrato, transpose, and bend modifiers behave exactly as they do on
+/// `Oscillator`, so the table can be imported from sampled single cycles or
+/// additive-synthesis results and then modulated like any other oscillator.
+pub struct WavetableOscillator {
+    table: Vec<Sample>,
+    lfo_intensity: f32,
+    transpose: f32,
+    bend: f32,
+    phase: f32,
+    phase_delta: f32,
+}
+
+impl WavetableOscillator {
+    /// Returns an oscillator playing the provided single-cycle wave table.
+    pub fn new(table: Vec<Sample>) -> Self {
+        WavetableOscillator {
+            table: table,
+            lfo_intensity: 0.0,
+            transpose: 1.0,
+            bend: 1.0,
+            phase: 0.0,
+            phase_delta: 0.0,
+        }
+    }
+
+    /// Sets the frequency of the waveform, and return the same oscillator.
+    pub fn freq(mut self, freq: f32) -> Self {
+        self.handle_message(WavetableMessage::SetFreq(freq));
+        self
+    }
+
+    /// Sets the frequency transposition (in steps), and return the same
+    /// oscillator.
+    pub fn transpose(mut self, steps: f32) -> Self {
+        self.handle_message(WavetableMessage::SetTranspose(steps));
+        self
+    }
+
+    /// Sets the intensity of the LFO vibrato, and return the same oscillator.
+    ///
+    /// The intensity is provided in half steps (1/2ths of an octave).
+    pub fn lfo_intensity(mut self, lfo_intensity: f32) -> Self {
+        self.handle_message(WavetableMessage::SetLFOIntensity(lfo_intensity));
+        self
+    }
+}
+
+impl MessageReceiver for WavetableOscillator {
+    type Msg = WavetableMessage;
+    fn handle_message(&mut self, msg: WavetableMessage) {
+        match msg {
+            WavetableMessage::SetFreq(freq) => {
+                self.phase_delta = freq*2.0*PI/(SAMPLE_RATE as f32);
+            },
+            WavetableMessage::SetWavetable(table) => {
+                self.table = table;
+            },
+            WavetableMessage::SetLFOIntensity(steps) => {
+                self.lfo_intensity = steps/12.0;
+            },
+            WavetableMessage::SetTranspose(steps) => {
+                self.transpose = 2.0.powf(steps/12.0);
+            },
+            WavetableMessage::SetBend(steps) => {
+                self.bend = 2.0.powf(steps/12.0);
+            },
+        }
+    }
+}
+
+impl AudioDevice for WavetableOscillator {
+    fn num_inputs(&self) -> usize {
+        1
+    }
+
+    fn num_outputs(&self) -> usize {
+        1
+    }
+
+    fn tick(&mut self, _: Time, inputs: &[Sample], outputs: &mut[Sample]) {
+        // Tick the phase, applying the same modifiers as Oscillator.
+        let phase_delta = if inputs.len() > 0 {
+            self.phase_delta*2.0.powf(inputs[0]*self.lfo_intensity)
+        } else {
+            self.phase_delta
+        } * self.bend * self.transpose;
+        self.phase += phase_delta;
+        if self.phase >= 2.0*PI {
+            self.phase -= 2.0*PI;
+        }
+
+        // Read the table at the normalized phase, interpolating linearly.
+        outputs[0] = if self.table.is_empty() {
+            0.0
+        } else {
+            let n = self.table.len();
+            let pos = self.phase/(2.0*PI) * n as f32;
+            let i = pos as usize % n;
+            let frac = pos - pos.floor();
+            self.table[i]*(1.0-frac) + self.table[(i+1) % n]*frac
+        };
+    }
+}
+
+
 /// Computes the PolyBLEP step for a given waveform type. This should be added
 /// to the naive waveform.
 ///
@@ -286,6 +529,16 @@ mod test {
               &[-0.8, -0.6, -0.4, -0.2, 0.0, 0.2, 0.4, 0.6, 0.8, -1.0]);
     }
 
+    #[test]
+    fn test_fast_sin() {
+        use super::fast_sin;
+        let mut x = -10.0;
+        while x < 10.0 {
+            assert!((fast_sin(x) - x.sin()).abs() < 1e-2);
+            x += 0.01;
+        }
+    }
+
     #[test]
     fn test_pulse() {
         let mut osc = Oscillator::new(Waveform::PulseTrain).freq(FREQ);
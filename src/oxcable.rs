@@ -9,15 +9,21 @@
 extern crate byteorder;
 
 pub mod adsr;
+pub mod clocked_queue;
 pub mod components;
+pub mod controller;
 pub mod delay;
 pub mod dynamics;
 pub mod filters;
+pub mod fm;
 pub mod init;
 pub mod instruments;
 pub mod io;
 pub mod mixers;
 pub mod oscillator;
+pub mod resampler;
 pub mod reverb;
+pub mod tween;
 pub mod types;
 pub mod utils;
+pub mod voice_array;
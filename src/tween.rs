@@ -0,0 +1,96 @@
+//! A per-sample parameter smoother.
+//!
+//! Setting a parameter like frequency or gain instantly produces audible
+//! clicks and zipper noise when the value is automated from MIDI or a
+//! sequencer. A `Tween` smooths the transition: it stores the current `actual`
+//! value, a `target`, and a per-sample `step`, and advances `actual` toward
+//! `target` once per frame. When `actual` crosses `target` it snaps exactly, so
+//! it never overshoots.
+//!
+//! ## Example
+//!
+//! ```
+//! use oxcable::tween::Tween;
+//! let mut freq = Tween::new(440.0);
+//! freq.set(880.0, 0.5); // glide to 880 Hz over half a second
+//! let value = freq.tick();
+//! ```
+
+use types::SAMPLE_RATE;
+
+
+/// A smoothly interpolated parameter value.
+#[derive(Clone, Copy, Debug)]
+pub struct Tween {
+    actual: f32,
+    target: f32,
+    step: f32,
+    min: Option<f32>,
+    max: Option<f32>,
+}
+
+impl Tween {
+    /// Returns a tween resting at `value`, with no bounds.
+    pub fn new(value: f32) -> Tween {
+        Tween {
+            actual: value,
+            target: value,
+            step: 0.0,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// Returns a tween resting at `value`, clamped to `[min, max]`.
+    pub fn with_bounds(value: f32, min: f32, max: f32) -> Tween {
+        Tween {
+            actual: value,
+            target: value,
+            step: 0.0,
+            min: Some(min),
+            max: Some(max),
+        }
+    }
+
+    /// Sets a new target, reaching it after `glide_time` seconds.
+    ///
+    /// A non-positive glide time sets the value immediately.
+    pub fn set(&mut self, target: f32, glide_time: f32) {
+        self.target = self.clamp(target);
+        if glide_time <= 0.0 {
+            self.actual = self.target;
+            self.step = 0.0;
+        } else {
+            self.step = (self.target - self.actual) / (glide_time*SAMPLE_RATE as f32);
+        }
+    }
+
+    /// Advances the value one frame toward the target, and returns it.
+    pub fn tick(&mut self) -> f32 {
+        self.actual += self.step;
+        let crossed = (self.step > 0.0 && self.actual >= self.target) ||
+            (self.step < 0.0 && self.actual <= self.target);
+        if crossed {
+            self.actual = self.target;
+            self.step = 0.0;
+        }
+        self.actual
+    }
+
+    /// Returns the current value without advancing it.
+    pub fn get(&self) -> f32 {
+        self.actual
+    }
+
+    /// Clamps a value to the configured bounds, if any.
+    fn clamp(&self, value: f32) -> f32 {
+        let mut value = value;
+        if let Some(min) = self.min {
+            if value < min { value = min; }
+        }
+        if let Some(max) = self.max {
+            if value > max { value = max; }
+        }
+        value
+    }
+}
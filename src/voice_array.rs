@@ -41,8 +41,16 @@ pub struct VoiceArray<T> {
     /// Places the most recently mapped voices at the end, and track the note
     /// they are currently playing
     held_voices: VecDeque<(usize, u8)>,
+    /// Voices that have been released but are still sounding out a decay, kept
+    /// out of the free pool until the implementor reports them silent
+    releasing_voices: VecDeque<usize>,
     /// Tracks free voices
     free_voices: VecDeque<usize>,
+    /// Whether the sustain pedal is currently engaged
+    sustain: bool,
+    /// Voices released while the pedal was down, kept out of the free pool
+    /// until the pedal lifts
+    sustained_voices: Vec<(usize, u8)>,
 }
 
 impl<T> VoiceArray<T> {
@@ -60,7 +68,10 @@ impl<T> VoiceArray<T> {
             voices: voices,
             note_to_voice: HashMap::new(),
             held_voices: VecDeque::new(),
+            releasing_voices: VecDeque::new(),
             free_voices: free_voices,
+            sustain: false,
+            sustained_voices: Vec::new(),
         }
     }
 
@@ -80,20 +91,28 @@ impl<T> VoiceArray<T> {
         let i = match self.note_to_voice.get(&note) {
             Some(&i) => {
                 // This note is already being played, so retrigger it and move
-                // it to the back of the queue
+                // it to the back of the queue. If it was sustained by the
+                // pedal, reclaim the same voice.
                 self.remove_from_held_queue(i);
+                self.remove_from_sustained(i);
                 i
             },
             None => {
                 let i = match self.free_voices.pop_front() {
                     // If there is a free voice, use the oldest one
                     Some(i) => i,
-                    // Otherwise, use the oldest playing voice.
-                    None => {
-                        // No free voices imply a held voice, so unwrap is safe.
-                        let (i, n) = self.held_voices.pop_front().unwrap();
-                        self.note_to_voice.remove(&n);
-                        i
+                    // Otherwise steal the oldest voice still decaying its
+                    // release, cutting it short rather than a held note.
+                    None => match self.releasing_voices.pop_front() {
+                        Some(i) => i,
+                        // Failing that, use the oldest held voice. With no free
+                        // or releasing voices, a held voice must exist.
+                        None => {
+                            let (i, n) = self.held_voices.pop_front().unwrap();
+                            self.note_to_voice.remove(&n);
+                            self.remove_from_sustained(i);
+                            i
+                        }
                     }
                 };
                 self.note_to_voice.insert(note, i);
@@ -119,6 +138,91 @@ impl<T> VoiceArray<T> {
         }
     }
 
+    /// Releases the voice playing `note` into the decaying pool and loans it
+    /// out, or returns `None` if no voice is playing it.
+    ///
+    /// Unlike [`note_off`](#method.note_off), the voice is *not* returned to the
+    /// free pool: it keeps its slot so the implementor can ramp down a release
+    /// envelope while it is still audible. Call [`free_silent`](#method.free_silent)
+    /// once the voice has faded to reclaim it.
+    pub fn release(&mut self, note: u8) -> Option<&mut T> {
+        match self.note_to_voice.remove(&note) {
+            Some(i) => {
+                self.remove_from_held_queue(i);
+                self.remove_from_sustained(i);
+                self.releasing_voices.push_back(i);
+                Some(&mut self.voices[i])
+            },
+            None => None
+        }
+    }
+
+    /// Returns releasing voices that `is_silent` reports as finished to the free
+    /// pool.
+    ///
+    /// The implementor owns the notion of "silent", so it is supplied as a
+    /// predicate over the voice object.
+    pub fn free_silent<F>(&mut self, is_silent: F) where F: Fn(&T) -> bool {
+        let mut still_releasing = VecDeque::with_capacity(self.releasing_voices.len());
+        while let Some(i) = self.releasing_voices.pop_front() {
+            if is_silent(&self.voices[i]) {
+                self.free_voices.push_back(i);
+            } else {
+                still_releasing.push_back(i);
+            }
+        }
+        self.releasing_voices = still_releasing;
+    }
+
+    /// Loans out the voice at `index` for modification.
+    pub fn voice_mut(&mut self, index: usize) -> &mut T {
+        &mut self.voices[index]
+    }
+
+    /// Sets the state of the sustain pedal, returning the voices that the
+    /// change releases.
+    ///
+    /// Lifting the pedal releases every voice that was held only by the pedal,
+    /// moving it into the decaying pool; the returned indices let the caller
+    /// start each release envelope (see [`voice_mut`](#method.voice_mut)).
+    /// Pressing the pedal releases nothing, so an empty vector is returned.
+    pub fn set_sustain(&mut self, on: bool) -> Vec<usize> {
+        self.sustain = on;
+        let mut released = Vec::new();
+        if !on {
+            while let Some((i, n)) = self.sustained_voices.pop() {
+                self.note_to_voice.remove(&n);
+                self.remove_from_held_queue(i);
+                self.releasing_voices.push_back(i);
+                released.push(i);
+            }
+        }
+        released
+    }
+
+    /// Handles a note off while honoring the sustain pedal.
+    ///
+    /// If the pedal is down, the note is marked as held-by-pedal and `None` is
+    /// returned: the voice keeps sounding until the pedal lifts (at which point
+    /// [`set_sustain`](#method.set_sustain) releases it). If the pedal is up,
+    /// this behaves exactly like [`release`](#method.release).
+    pub fn sustained_note_off(&mut self, note: u8) -> Option<&mut T> {
+        if !self.sustain {
+            return self.release(note);
+        }
+        if let Some(&i) = self.note_to_voice.get(&note) {
+            if !self.sustained_voices.iter().any(|&(j, _)| j == i) {
+                self.sustained_voices.push((i, note));
+            }
+        }
+        None
+    }
+
+    // Removes a voice from the sustained set, if present.
+    fn remove_from_sustained(&mut self, voice: usize) {
+        self.sustained_voices.retain(|&(i, _)| i != voice);
+    }
+
     // Finds a voice in the held queue and removes it.
     fn remove_from_held_queue(&mut self, voice: usize) {
         for i in 0..self.held_voices.len() {
@@ -188,6 +292,32 @@ mod test {
         assert_eq!(v4, v2);
     }
 
+    /// Verify that a sustained voice is not freed until the pedal lifts, and
+    /// that retriggering it reclaims the same voice.
+    #[test]
+    fn test_sustain_pedal() {
+        use super::VoiceArray;
+        let mut voices = VoiceArray::new(vec![1, 2]);
+        voices.set_sustain(true);
+        let v1 = voices.note_on(1).clone();
+        voices.sustained_note_off(1);
+
+        // While sustained, retriggering note 1 reclaims the same voice.
+        let v1b = voices.note_on(1).clone();
+        assert_eq!(v1, v1b);
+
+        // A second note takes the other voice while 1 is still sustained.
+        voices.sustained_note_off(1);
+        let v2 = voices.note_on(2).clone();
+        assert!(v1 != v2);
+
+        // Lifting the pedal releases the sustained voice, so the next note
+        // reuses it.
+        voices.set_sustain(false);
+        let v3 = voices.note_on(3).clone();
+        assert_eq!(v3, v1);
+    }
+
     /// Verify that note pruning always selects the oldest held voice.
     #[test]
     fn test_oldest_held() {